@@ -0,0 +1,37 @@
+use crate::{context::Context, util::cache::days};
+use std::time::SystemTime;
+
+pub(crate) async fn main(purge: bool, older_than: Option<u64>, context: &Context) {
+    let cache = context.cache();
+    if purge {
+        match cache.purge(older_than).await {
+            Ok((count, size)) => context.report_info(&format!(
+                "Removed {} cached image(s) of {} bytes",
+                count, size
+            )),
+            Err(error) => context.report_error(&format!("failed to purge cache: {}", error)),
+        }
+        return;
+    }
+
+    let entries = match cache.entries().await {
+        Ok(entries) => entries,
+        Err(error) => {
+            context.report_error(&format!("failed to read cache: {}", error));
+            return;
+        }
+    };
+    println!("directory: {}", cache.dir().display());
+    println!("images: {}", entries.len());
+    println!(
+        "size: {} bytes",
+        entries.iter().map(|x| x.size).sum::<u64>()
+    );
+    if let Some(entry) = entries.first() {
+        let age = SystemTime::now()
+            .duration_since(entry.used_at)
+            .map(|x| x.as_secs() / days(1).as_secs())
+            .unwrap_or(0);
+        println!("least recently used: {} day(s) ago", age);
+    }
+}
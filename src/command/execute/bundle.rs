@@ -0,0 +1,59 @@
+use super::Element;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub(super) struct BundleJson {
+    max_pages: Option<usize>,
+    /// Max size in MB of page images.
+    max_size: Option<u64>,
+}
+
+impl BundleJson {
+    /// Group elements of the same comic in order, and split each group into bundles within the
+    /// limits. A chapter exceeding the limits alone is put in a bundle of its own.
+    pub fn group(&self, elements: Vec<Element>) -> Vec<Vec<Element>> {
+        let mut comics: Vec<Vec<Element>> = vec![];
+        for element in elements {
+            match comics.iter_mut().find(|x| {
+                x[0].source_tag == element.source_tag && x[0].comic_id == element.comic_id
+            }) {
+                Some(comic) => comic.push(element),
+                None => comics.push(vec![element]),
+            }
+        }
+
+        let max_size = self.max_size.map(|x| x * 1024 * 1024);
+        let mut bundles = vec![];
+        for comic in comics {
+            let mut bundle: Vec<Element> = vec![];
+            let mut pages = 0;
+            let mut size = 0;
+            for element in comic {
+                let element_pages = element.images.len();
+                let element_size = element
+                    .images
+                    .iter()
+                    .filter_map(|x| std::fs::metadata(x).ok())
+                    .map(|x| x.len())
+                    .sum::<u64>();
+                let exceeded = self
+                    .max_pages
+                    .map(|x| pages + element_pages > x)
+                    .unwrap_or(false)
+                    || max_size.map(|x| size + element_size > x).unwrap_or(false);
+                if exceeded && !bundle.is_empty() {
+                    bundles.push(std::mem::take(&mut bundle));
+                    pages = 0;
+                    size = 0;
+                }
+                pages += element_pages;
+                size += element_size;
+                bundle.push(element);
+            }
+            if !bundle.is_empty() {
+                bundles.push(bundle);
+            }
+        }
+        return bundles;
+    }
+}
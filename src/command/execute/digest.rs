@@ -0,0 +1,110 @@
+use super::HOLDER_COMIC_NAME;
+use crate::state::ChapterState;
+use const_format::formatcp;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+pub(super) const NOTIFY_DIGEST_TITLE: &str = "Comic Digest";
+pub(self) const HOLDER_CHAPTERS: &str = "%chapters%";
+pub(self) const HOLDER_DELIVERED_COUNT: &str = "%delivered%";
+pub(self) const HOLDER_FAILED_COUNT: &str = "%failed%";
+pub(self) const HOLDER_SUMMARY: &str = "%summary%";
+pub(self) const HOLDER_CHAPTER_COUNT: &str = "%count%";
+pub(self) const HOLDER_COMIC_COUNT: &str = "%comics%";
+pub(self) const DEFAULT_DIGEST_COMIC_TEMPLATE: &str = formatcp!(
+    "{}: {} ({} delivered, {} failed)",
+    HOLDER_COMIC_NAME,
+    HOLDER_CHAPTERS,
+    HOLDER_DELIVERED_COUNT,
+    HOLDER_FAILED_COUNT
+);
+pub(self) const DEFAULT_DIGEST_TEMPLATE: &str = formatcp!(
+    "{} chapter(s) of {} comic(s) updated.\n{}",
+    HOLDER_CHAPTER_COUNT,
+    HOLDER_COMIC_COUNT,
+    HOLDER_SUMMARY
+);
+
+#[derive(Deserialize)]
+pub(super) struct DigestJson {
+    template: Option<String>,
+    comic: Option<String>,
+    #[serde(default)]
+    mail: Vec<String>,
+}
+
+impl DigestJson {
+    pub fn mail(&self) -> &[String] {
+        return &self.mail;
+    }
+}
+
+#[derive(Default)]
+pub(super) struct ComicDigest {
+    chapters: Vec<String>,
+    delivered: usize,
+    failed: usize,
+}
+
+/// Aggregate updates of a run into a summary.
+#[derive(Default)]
+pub(super) struct Digest {
+    comics: BTreeMap<String, ComicDigest>,
+    count: usize,
+}
+
+impl Digest {
+    pub fn add(&mut self, chapter: &ChapterState) {
+        let comic = self
+            .comics
+            .entry(
+                chapter
+                    .comic_name
+                    .clone()
+                    .unwrap_or(chapter.comic_id.clone()),
+            )
+            .or_default();
+        comic.chapters.push(
+            chapter
+                .chapter_name
+                .clone()
+                .unwrap_or(chapter.chapter_id.clone()),
+        );
+        if chapter.delivered_count() == chapter.deliveries.len() {
+            comic.delivered += 1;
+        } else {
+            comic.failed += 1;
+        }
+        self.count += 1;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.count == 0;
+    }
+
+    pub fn content(&self, json: &DigestJson) -> String {
+        let comic_template = json
+            .comic
+            .as_deref()
+            .unwrap_or(DEFAULT_DIGEST_COMIC_TEMPLATE);
+        let summary = self
+            .comics
+            .iter()
+            .map(|(name, comic)| {
+                comic_template
+                    .replace(HOLDER_COMIC_NAME, name)
+                    .replace(HOLDER_CHAPTERS, &comic.chapters.join(", "))
+                    .replace(HOLDER_DELIVERED_COUNT, &comic.delivered.to_string())
+                    .replace(HOLDER_FAILED_COUNT, &comic.failed.to_string())
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        return json
+            .template
+            .as_deref()
+            .unwrap_or(DEFAULT_DIGEST_TEMPLATE)
+            .replace(HOLDER_CHAPTER_COUNT, &self.count.to_string())
+            .replace(HOLDER_COMIC_COUNT, &self.comics.len().to_string())
+            .replace(HOLDER_SUMMARY, &summary);
+    }
+}
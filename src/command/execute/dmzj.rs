@@ -1,16 +1,26 @@
-use super::{Element, Source};
-use crate::{context::Context, model};
+use super::{
+    DocumentFormat, Element, SearchResult, Source, FAILURE_CHAPTER_INCOMPLETE,
+    FAILURE_SOURCE_UNREACHABLE,
+};
+use crate::{
+    context::{Context, Severity},
+    model,
+    util::image::{check_decodable, verify_image, CropJson, Spread},
+};
 use async_trait::async_trait;
 use futures::future::join_all;
 use serde::Deserialize;
 use serde_json::Value;
-use std::path::{Path, PathBuf};
+use std::{error::Error, path::PathBuf};
 
 pub(super) struct DmzjSource {}
 
 #[derive(Deserialize)]
 pub(self) struct DmzjChannel {
     pub id: String,
+    pub format: Option<DocumentFormat>,
+    pub crop: Option<CropJson>,
+    pub spread: Option<Spread>,
 }
 
 #[async_trait]
@@ -34,11 +44,23 @@ impl Source for DmzjSource {
         let futures = channels.into_iter().map(|channel| async move {
             // Fetch comic information.
             let comic_id = &channel.id;
+            let format = channel.format;
+            let crop = channel.crop;
+            let spread = channel.spread;
             context.report_debug(&format!("fetching comic {}", comic_id));
-            let comic_info = match model::dmzj::search_comic(comic_id).await {
+            let comic_info = match model::dmzj::search_comic(context.http(), comic_id)
+                .await
+                .map_err(|x| x.to_string())
+            {
                 Ok(comic_info) => comic_info,
                 Err(error) => {
-                    context.report_error(&format!("failed to search comic: {}", error));
+                    context
+                        .report_failure(
+                            Severity::Error,
+                            FAILURE_SOURCE_UNREACHABLE,
+                            &format!("failed to search comic {}: {}", comic_id, error),
+                        )
+                        .await;
                     return None; // 'channel
                 }
             };
@@ -65,70 +87,136 @@ impl Source for DmzjSource {
 
                 let images = if !learn {
                     // Only fetch and download image if not in learn mode.
+                    let _permit = context.http().scheduler().chapter().await;
+                    if context.is_shutting_down() {
+                        context.report_debug(&format!(
+                            "skip chapter {}:{} because of shutdown",
+                            comic_id, chapter_id
+                        ));
+                        return None; // 'chapter
+                    }
                     context.report_debug(&format!("fetching chapter {}:{}", comic_id, chapter_id));
-                    let chapter_info = match model::dmzj::search_chapter(comic_id, chapter_id).await
-                    {
-                        Ok(chapter_info) => chapter_info,
-                        Err(error) => {
-                            context.report_error(&format!("failed to search chapter: {}", error));
-                            return None; // 'chapter
-                        }
-                    };
+                    let chapter_info =
+                        match model::dmzj::search_chapter(context.http(), comic_id, chapter_id)
+                            .await
+                            .map_err(|x| x.to_string())
+                        {
+                            Ok(chapter_info) => chapter_info,
+                            Err(error) => {
+                                context
+                                    .report_failure(
+                                        Severity::Error,
+                                        FAILURE_SOURCE_UNREACHABLE,
+                                        &format!(
+                                            "failed to search chapter {}:{}: {}",
+                                            comic_id, chapter_id, error
+                                        ),
+                                    )
+                                    .await;
+                                return None; // 'chapter
+                            }
+                        };
 
-                    // Download comic images.
+                    // Download comic images, pages downloaded by the interrupted run are kept
+                    // in the journal.
+                    let journal = context.open_journal(self.tag(), comic_id, chapter_id).await;
+                    let resumed = journal.len().await;
+                    if resumed > 0 {
+                        context.report_info(&format!(
+                            "Resume chapter {}:{} with {} downloaded page(s)",
+                            comic_id, chapter_id, resumed
+                        ));
+                    }
+                    let journal = &journal;
                     let futures =
                         chapter_info
                             .pages
                             .iter()
                             .enumerate()
                             .map(|(index, url)| async move {
-                                let extension =
-                                    match Path::new(&url).extension().and_then(|e| e.to_str()) {
-                                        Some(extension) => extension,
-                                        None => {
-                                            context.report_error(&format!(
-                                                "cannot parse extension from url {}",
-                                                &url
-                                            ));
-                                            return None; // 'page
-                                        }
-                                    };
-                                let path = match context
-                                    .create_image_cache(
-                                        self.tag(),
-                                        comic_id,
-                                        chapter_id,
-                                        index,
-                                        extension,
-                                    )
-                                    .await
-                                {
-                                    Ok(path) => path,
-                                    Err(error) => {
-                                        context.report_error(&format!(
-                                            "failed to create image file: {}",
-                                            error
-                                        ));
-                                        return None; // 'page
-                                    }
+                                if let Some(path) = journal.page(index, url).await {
+                                    context.report_debug(&format!("resume downloaded {}", url));
+                                    return Some(path); // 'page
+                                }
+                                let path = match download_page(url, context).await {
+                                    Some(path) => path,
+                                    None => return None, // 'page
                                 };
-                                if let Err(error) =
-                                    model::dmzj::download_image(path.as_path(), &url).await
-                                {
+                                if let Err(error) = journal.record(index, url, &path).await {
                                     context.report_error(&format!(
-                                        "failed to download image: {}",
-                                        error
+                                        "failed to write journal of chapter {}:{}: {}",
+                                        comic_id, chapter_id, error
                                     ));
-                                    return None; // 'page
-                                } else {
-                                    return Some(path); // 'page
                                 }
+                                return Some(path); // 'page
                             });
-                    join_all(futures)
-                        .await
-                        .into_iter()
-                        .flatten()
-                        .collect::<Vec<PathBuf>>()
+                    let pages = join_all(futures).await;
+
+                    // Skip the chapter if any page is missing, it will be fetched again on the
+                    // next run because it is not marked.
+                    let missing = pages
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, page)| page.is_none())
+                        .map(|(index, _)| (index + 1).to_string())
+                        .collect::<Vec<String>>();
+                    if pages.is_empty() {
+                        context
+                            .report_failure(
+                                Severity::Warning,
+                                FAILURE_CHAPTER_INCOMPLETE,
+                                &format!(
+                                    "skip chapter {}:{} because it has no page",
+                                    comic_id, chapter_id
+                                ),
+                            )
+                            .await;
+                        return None; // 'chapter
+                    }
+                    if !missing.is_empty() {
+                        context
+                            .report_failure(
+                                Severity::Warning,
+                                FAILURE_CHAPTER_INCOMPLETE,
+                                &format!(
+                                    "skip chapter {}:{} because {} of {} pages failed to download: {}",
+                                    comic_id,
+                                    chapter_id,
+                                    missing.len(),
+                                    pages.len(),
+                                    missing.join(", ")
+                                ),
+                            )
+                            .await;
+                        return None; // 'chapter
+                    }
+
+                    // Skip the chapter if any page is in a format not supported by this build,
+                    // like AVIF without feature avif, same as a page failed to download.
+                    let mut unsupported = vec![];
+                    for (index, page) in pages.iter().flatten().enumerate() {
+                        if let Err(error) = check_decodable(page).await.map_err(|x| x.to_string()) {
+                            unsupported.push(format!("{} ({})", index + 1, error));
+                        }
+                    }
+                    if !unsupported.is_empty() {
+                        context
+                            .report_failure(
+                                Severity::Warning,
+                                FAILURE_CHAPTER_INCOMPLETE,
+                                &format!(
+                                    "skip chapter {}:{} because {} of {} pages cannot be decoded: {}",
+                                    comic_id,
+                                    chapter_id,
+                                    unsupported.len(),
+                                    pages.len(),
+                                    unsupported.join(", ")
+                                ),
+                            )
+                            .await;
+                        return None; // 'chapter
+                    }
+                    pages.into_iter().flatten().collect::<Vec<PathBuf>>()
                 } else {
                     vec![]
                 };
@@ -138,6 +226,9 @@ impl Source for DmzjSource {
                     comic_name: comic_name.to_string(),
                     chapter_id: chapter_id.clone(),
                     chapter_name: chapter.title,
+                    format,
+                    crop,
+                    spread,
                     images,
                 });
             });
@@ -156,4 +247,53 @@ impl Source for DmzjSource {
             .flatten()
             .collect();
     }
+    async fn search(
+        &self,
+        keyword: &str,
+        context: &Context,
+    ) -> Result<Vec<SearchResult>, Box<dyn Error>> {
+        context.report_debug(&format!(
+            "searching keyword {} from {}",
+            keyword,
+            self.tag()
+        ));
+        let results = model::dmzj::search_keyword(context.http(), keyword)
+            .await?
+            .into_iter()
+            .map(|info| SearchResult {
+                comic_id: info.id,
+                title: info.title,
+                author: info.author,
+                status: info.status,
+                latest_chapter: info.latest_chapter,
+            })
+            .collect::<Vec<SearchResult>>();
+        return Ok(results);
+    }
+}
+
+/// Find the page in cache, or download it if it is not cached or the cached image is broken.
+async fn download_page(url: &str, context: &Context) -> Option<PathBuf> {
+    if let Some(path) = context.cache().find(url).await {
+        if verify_image(&path).await {
+            context.report_debug(&format!("hit cache of {}", url));
+            return Some(path);
+        }
+        context.report_debug(&format!("discard broken cache of {}", url));
+        let _ = tokio::fs::remove_file(&path).await;
+    }
+    let path = match context.cache().path(url).await {
+        Ok(path) => path,
+        Err(error) => {
+            context.report_error(&format!("failed to create image file: {}", error));
+            return None;
+        }
+    };
+    return match model::dmzj::download_image(context.http(), path.as_path(), url).await {
+        Ok(path) => Some(path),
+        Err(error) => {
+            context.report_error(&format!("failed to download image {}: {}", url, error));
+            None
+        }
+    };
 }
@@ -1,18 +1,43 @@
+mod bundle;
+mod digest;
 mod dmzj;
+mod receiver;
 
 use crate::{
-    context::Context,
+    context::{Context, FailureNotify, Severity, HOLDER_FAILURE_EVENT, HOLDER_FAILURE_MESSAGE},
     mail::{Mailbox, MailboxJson},
-    util::{extension::ResultExtension, pdf::create_pdf_from_images},
+    notify::{Notifier, NotifierJson},
+    state::ChapterState,
+    util::{
+        cache::{Cache, CacheJson},
+        cbz::create_cbz_from_images,
+        device::{Device, DeviceJson},
+        document::{self, DocumentChapter},
+        epub::create_epub_from_images,
+        extension::ResultExtension,
+        http::{Http, HttpJson, ProxyJson},
+        image::{CropJson, ImageJson, ImageProcess, Spread},
+        pdf::create_pdf_from_images,
+        schedule::{ScheduleJson, Scheduler},
+    },
     APP_NAME_TITLEIZE,
 };
 use async_trait::async_trait;
+use bundle::BundleJson;
 use const_format::formatcp;
+use digest::{Digest, DigestJson, NOTIFY_DIGEST_TITLE};
 use futures::future::join_all;
+use receiver::{Receiver, ReceiverJson};
 use serde::Deserialize;
 use serde_json::Value;
-use std::{error::Error, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    path::{Path, PathBuf},
+};
+use tokio::runtime::Handle;
 
+pub(self) const DEFAULT_MAX_ATTEMPTS: u32 = 3;
 pub(self) const NOTIFY_UPDATE_TITLE: &str = "Comic Update";
 pub(self) const HOLDER_COMIC_NAME: &str = "%comic%";
 pub(self) const HOLDER_CHAPTER_NAME: &str = "%chapter%";
@@ -26,14 +51,181 @@ pub(self) const DEFAULT_NOTIFY_CONTENT_TEMPLATE: &str = formatcp!(
     HOLDER_TOTAL_COUNT
 );
 
+pub(self) const NOTIFY_FAILURE_TITLE: &str = "Comic Failure";
+pub(self) const DEFAULT_NOTIFY_FAILURE_TEMPLATE: &str = formatcp!(
+    "Comik failed with {}: {}",
+    HOLDER_FAILURE_EVENT,
+    HOLDER_FAILURE_MESSAGE
+);
+pub(self) const FAILURE_SOURCE_UNREACHABLE: &str = "source unreachable";
+pub(self) const FAILURE_CHAPTER_INCOMPLETE: &str = "chapter incomplete";
+pub(self) const FAILURE_DOCUMENT: &str = "document not created";
+pub(self) const FAILURE_MAIL_REJECTED: &str = "mail rejected";
+
+#[derive(Deserialize, clap::ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum DocumentFormat {
+    Pdf,
+    Epub,
+    Cbz,
+}
+
+impl DocumentFormat {
+    fn extension(&self) -> &'static str {
+        return match self {
+            DocumentFormat::Pdf => "pdf",
+            DocumentFormat::Epub => "epub",
+            DocumentFormat::Cbz => "cbz",
+        };
+    }
+
+    async fn create(
+        &self,
+        name: &str,
+        parent_dir: &Path,
+        series: &str,
+        chapters: &[DocumentChapter],
+        scale: f64,
+        device: &Device,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        // Encoding documents is CPU-bound, which runs on a blocking thread to keep the runtime
+        // responsive, like handling shutdown signals.
+        let format = *self;
+        let (name, parent_dir, series) = (
+            name.to_string(),
+            parent_dir.to_path_buf(),
+            series.to_string(),
+        );
+        let chapters = chapters.to_vec();
+        let device = *device;
+        let result = tokio::task::spawn_blocking(move || {
+            let create = async {
+                return match format {
+                    DocumentFormat::Pdf => {
+                        create_pdf_from_images(&name, &parent_dir, &chapters, scale, &device).await
+                    }
+                    DocumentFormat::Epub => {
+                        create_epub_from_images(&name, &parent_dir, &chapters, scale, &device).await
+                    }
+                    DocumentFormat::Cbz => {
+                        create_cbz_from_images(&name, &parent_dir, &chapters, &series).await
+                    }
+                };
+            };
+            return Handle::current()
+                .block_on(create)
+                .map_err(|x| x.to_string());
+        })
+        .await?;
+        return result.map_err(|x| x.into());
+    }
+
+    /// Create document, which is split into numbered parts if its size exceeds the limit.
+    #[allow(clippy::too_many_arguments)]
+    async fn create_within(
+        &self,
+        name: &str,
+        parent_dir: &Path,
+        series: &str,
+        chapters: &[DocumentChapter],
+        scale: f64,
+        device: &Device,
+        max_size: Option<u64>,
+    ) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        let file = self
+            .create(name, parent_dir, series, chapters, scale, device)
+            .await?;
+        let size = tokio::fs::metadata(&file).await?.len();
+        let pages = chapters.iter().map(|x| x.images.len()).sum::<usize>();
+        let max_size = match max_size {
+            Some(max_size) if max_size > 0 && size > max_size && pages > 1 => max_size,
+            _ => return Ok(vec![file]),
+        };
+        tokio::fs::remove_file(&file).await?;
+
+        // Split into more parts until every part is within the limit or has only one page.
+        let stem = Path::new(name)
+            .file_stem()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or(name.to_string());
+        let mut count = size.div_ceil(max_size) as usize;
+        loop {
+            count = count.clamp(2, pages);
+            let mut files = vec![];
+            let mut exceeded = false;
+            for (index, part) in document::split(chapters, count).iter().enumerate() {
+                let file = self
+                    .create(
+                        &format!("{} Part {}.{}", stem, index + 1, self.extension()),
+                        parent_dir,
+                        series,
+                        part,
+                        scale,
+                        device,
+                    )
+                    .await?;
+                let part_pages = part.iter().map(|x| x.images.len()).sum::<usize>();
+                if tokio::fs::metadata(&file).await?.len() > max_size && part_pages > 1 {
+                    exceeded = true;
+                }
+                files.push(file);
+            }
+            if !exceeded || count == pages {
+                return Ok(files);
+            }
+            for file in files {
+                tokio::fs::remove_file(file).await?;
+            }
+            count *= 2;
+        }
+    }
+}
+
 #[derive(Deserialize)]
 pub(self) struct ConfigJson {
     sender: MailboxJson,
-    receivers: Vec<String>,
+    receivers: Vec<ReceiverJson>,
+    #[serde(default)]
+    devices: BTreeMap<String, DeviceJson>,
+    max_attempts: Option<u32>,
+    #[serde(default)]
+    http: HttpJson,
+    #[serde(default)]
+    proxy: ProxyJson,
+    #[serde(default)]
+    concurrency: ScheduleJson,
+    #[serde(default)]
+    cache: CacheJson,
+    #[serde(default)]
+    notifiers: Vec<NotifierJson>,
     notify: Option<String>,
+    digest: Option<DigestJson>,
+    #[serde(default)]
+    failure: FailureJson,
+    bundle: Option<BundleJson>,
+    #[serde(default)]
+    image: ImageJson,
     source: Value,
 }
 
+#[derive(Deserialize)]
+#[serde(default)]
+pub(self) struct FailureJson {
+    enabled: bool,
+    template: Option<String>,
+    severity: Severity,
+}
+
+impl Default for FailureJson {
+    fn default() -> Self {
+        return FailureJson {
+            enabled: true,
+            template: None,
+            severity: Severity::Error,
+        };
+    }
+}
+
 impl ConfigJson {
     async fn read(path: PathBuf) -> Result<ConfigJson, Box<dyn Error>> {
         let json = tokio::fs::read_to_string(path).await?;
@@ -43,7 +235,8 @@ impl ConfigJson {
 
 pub(self) struct Config {
     sender: Mailbox,
-    receivers: Vec<String>,
+    receivers: Vec<Receiver>,
+    max_attempts: u32,
 }
 
 impl TryFrom<&ConfigJson> for Config {
@@ -52,35 +245,80 @@ impl TryFrom<&ConfigJson> for Config {
     fn try_from(json: &ConfigJson) -> Result<Self, Box<dyn Error>> {
         return Config {
             sender: Mailbox::try_from(&json.sender)?,
-            receivers: json.receivers.clone(),
+            receivers: json
+                .receivers
+                .iter()
+                .map(|x| Receiver::new(x, &json.devices))
+                .collect::<Result<Vec<Receiver>, Box<dyn Error>>>()?,
+            max_attempts: json.max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS),
         }
         .into_ok();
     }
 }
 
 #[async_trait]
-pub(self) trait Source {
+pub(crate) trait Source {
     fn tag(&self) -> &'static str;
     async fn fetch(&self, learn: bool, value: &Value, context: &Context) -> Vec<Element>;
+    async fn search(
+        &self,
+        keyword: &str,
+        context: &Context,
+    ) -> Result<Vec<SearchResult>, Box<dyn Error>>;
 }
 
-fn find_source(tag: &str) -> Option<Box<dyn Source>> {
-    return match tag {
-        "dmzj" => Some(Box::new(dmzj::DmzjSource {})),
-        _ => None,
-    };
+/// All supported sources.
+pub(crate) fn sources() -> Vec<Box<dyn Source>> {
+    return vec![Box::new(dmzj::DmzjSource {})];
+}
+
+pub(crate) fn find_source(tag: &str) -> Option<Box<dyn Source>> {
+    return sources().into_iter().find(|x| x.tag() == tag);
 }
 
-pub(self) struct Element {
+pub(crate) struct SearchResult {
+    pub comic_id: String,
+    pub title: String,
+    pub author: String,
+    pub status: String,
+    pub latest_chapter: String,
+}
+
+pub(crate) struct Element {
     source_tag: &'static str,
     comic_id: String,
     comic_name: String,
     chapter_id: String,
     chapter_name: String,
+    format: Option<DocumentFormat>,
+    crop: Option<CropJson>,
+    spread: Option<Spread>,
     images: Vec<PathBuf>,
 }
 
-pub(crate) async fn main(learn: bool, scale: f64, config: PathBuf, context: &Context) {
+impl Element {
+    fn chapter_state(&self) -> ChapterState {
+        let mut chapter = ChapterState::new(self.source_tag, &self.comic_id, &self.chapter_id);
+        chapter.comic_name = Some(self.comic_name.clone());
+        chapter.chapter_name = Some(self.chapter_name.clone());
+        return chapter;
+    }
+
+    fn document_chapter(&self) -> DocumentChapter {
+        return DocumentChapter {
+            title: self.chapter_name.clone(),
+            images: self.images.clone(),
+        };
+    }
+}
+
+pub(crate) async fn main(
+    learn: bool,
+    scale: f64,
+    format: DocumentFormat,
+    config: PathBuf,
+    context: &mut Context,
+) {
     let config_json = {
         let config_json = ConfigJson::read(config).await;
         if let Err(error) = config_json {
@@ -89,6 +327,47 @@ pub(crate) async fn main(learn: bool, scale: f64, config: PathBuf, context: &Con
         }
         config_json.unwrap()
     };
+    match Http::new(
+        &config_json.http,
+        &config_json.proxy,
+        Scheduler::from(&config_json.concurrency),
+    ) {
+        Ok(http) => context.set_http(http),
+        Err(error) => {
+            context.report_error(&format!("failed to create HTTP client: {}", error));
+            return;
+        }
+    }
+    context.set_cache(Cache::new(
+        context.cache().dir().to_path_buf(),
+        &config_json.cache,
+    ));
+    context.add_notifiers(
+        config_json
+            .notifiers
+            .iter()
+            .map(Box::<dyn Notifier>::from)
+            .collect(),
+    );
+    context.set_failure_notify(if config_json.failure.enabled {
+        Some(FailureNotify {
+            title: NOTIFY_FAILURE_TITLE.to_string(),
+            template: config_json
+                .failure
+                .template
+                .clone()
+                .unwrap_or(DEFAULT_NOTIFY_FAILURE_TEMPLATE.to_string()),
+            threshold: config_json.failure.severity,
+        })
+    } else {
+        None
+    });
+    let context = &*context;
+
+    if let Err(error) = context.load_state().await {
+        context.report_error(&format!("failed to load state: {}", error));
+        return;
+    }
 
     if !config_json.source.is_object() {
         context.report_error("the source property parsed from config file is not an object");
@@ -128,55 +407,328 @@ pub(crate) async fn main(learn: bool, scale: f64, config: PathBuf, context: &Con
     };
     let sender = &config.sender;
     let receivers = &config.receivers;
+    let max_attempts = config.max_attempts;
     let notify = &config_json.notify;
-    let futures = elements.into_iter().map(|element| async move {
-        if !learn {
-            let file = create_pdf_from_images(
-                &format!("{} {}.pdf", &element.comic_name, &element.chapter_name),
-                context.document_repo_path(),
-                &element.images,
-                scale,
+    let digest = &config_json.digest;
+    let mut updates: Vec<ChapterState> = vec![];
+
+    // Only mark chapters in learn mode.
+    if learn {
+        for element in &elements {
+            context.report_info(&format!(
+                "Skip creating document for {}:{} in learn mode",
+                &element.comic_id, &element.chapter_id
+            ));
+        }
+        mark(
+            &elements
+                .iter()
+                .map(|x| x.chapter_state())
+                .collect::<Vec<ChapterState>>(),
+            context,
+        )
+        .await;
+        return;
+    }
+
+    // Retry deliveries failed in previous runs, chapters bundled in the same document are
+    // retried together.
+    let mut pending: BTreeMap<BTreeMap<Vec<PathBuf>, Vec<String>>, Vec<ChapterState>> =
+        BTreeMap::new();
+    for chapter in context.pending_chapters(max_attempts) {
+        let documents = chapter.pending_documents(max_attempts);
+        if !documents.is_empty() {
+            pending.entry(documents).or_default().push(chapter);
+        }
+    }
+    let futures = pending
+        .into_iter()
+        .map(|(documents, mut chapters)| async move {
+            for (files, receivers) in documents {
+                if context.is_shutting_down() {
+                    break;
+                }
+                context.report_info(&format!(
+                    "Retry sending {} to {} receiver(s)",
+                    files[0].display(),
+                    receivers.len()
+                ));
+                deliver(
+                    &mut chapters,
+                    &files,
+                    &receivers,
+                    sender,
+                    max_attempts,
+                    context,
+                )
+                .await;
+            }
+            mark(&chapters, context).await;
+            if digest.is_none() {
+                let content = notify_content(notify, &chapters);
+                context.notify(NOTIFY_UPDATE_TITLE, &content).await;
+            }
+            return chapters;
+        });
+    updates.extend(join_all(futures).await.into_iter().flatten());
+
+    let image = ImageProcess::from(&config_json.image);
+
+    // Bundle chapters of the same comic into one document if enabled.
+    let bundles = match &config_json.bundle {
+        Some(bundle) => bundle.group(elements),
+        None => elements.into_iter().map(|x| vec![x]).collect(),
+    };
+    let futures = bundles.into_iter().map(|bundle| async move {
+        let first = &bundle[0];
+        let last = &bundle[bundle.len() - 1];
+        let variants = receiver::variants(receivers, first, first.format.unwrap_or(format), scale);
+        if variants.is_empty() {
+            context.report_info(&format!(
+                "Skip creating document for {}:{} wanted by no receiver",
+                &first.comic_id, &first.chapter_id
+            ));
+            mark(
+                &bundle
+                    .iter()
+                    .map(|x| x.chapter_state())
+                    .collect::<Vec<ChapterState>>(),
+                context,
             )
             .await;
-            if let Err(error) = file {
-                context.report_error(&format!("failed to create document: {}", error));
-                return;
+            return vec![];
+        }
+        let stem = if bundle.len() == 1 {
+            format!("{} {}", &first.comic_name, &first.chapter_name)
+        } else {
+            format!(
+                "{} {} - {}",
+                &first.comic_name, &first.chapter_name, &last.chapter_name
+            )
+        };
+        let document_chapters = bundle
+            .iter()
+            .map(|x| x.document_chapter())
+            .collect::<Vec<DocumentChapter>>();
+        let addresses = variants
+            .iter()
+            .flat_map(|x| x.receivers.clone())
+            .collect::<Vec<String>>();
+        let mut chapters = bundle
+            .iter()
+            .map(|x| {
+                let mut chapter = x.chapter_state();
+                chapter.add_receivers(&addresses);
+                chapter
+            })
+            .collect::<Vec<ChapterState>>();
+
+        // Create each variant of document once for receivers wanting it. Chapters are fetched
+        // again in the next run if shutdown before all variants are created. Failure to create
+        // a variant is final for its receivers, while other variants are still delivered.
+        let mut documents = vec![];
+        for variant in &variants {
+            if context.is_shutting_down() {
+                context.report_info(&format!(
+                    "Skip creating document for {}:{} because of shutdown",
+                    &first.comic_id, &first.chapter_id
+                ));
+                return vec![];
             }
-            let file = file.unwrap();
-            let mut success = 0;
-            for receiver in receivers {
-                if let Err(error) = sender.send_file(receiver, APP_NAME_TITLEIZE, &file).await {
+            let name = format!(
+                "{}{}.{}",
+                stem,
+                variant.label(&variants),
+                variant.format.extension()
+            );
+            let files = match image
+                .with_crop(first.crop.as_ref())
+                .with_spread(first.spread)
+                .for_device(&variant.device)
+                .process_chapters(&document_chapters)
+                .await
+            {
+                Ok(document_chapters) => {
+                    variant
+                        .format
+                        .create_within(
+                            &name,
+                            context.document_repo_path(),
+                            &first.comic_name,
+                            &document_chapters,
+                            variant.scale,
+                            &variant.device,
+                            sender.max_attachment_size(),
+                        )
+                        .await
+                }
+                Err(error) => Err(error),
+            };
+            let files = match files.map_err(|x| x.to_string()) {
+                Ok(files) => files,
+                Err(error) => {
+                    let error = format!("failed to create document {}: {}", name, error);
                     context
-                        .report_error(&format!("failed to send mail to {}: {}", receiver, error));
-                } else {
-                    success += 1;
+                        .report_failure(Severity::Error, FAILURE_DOCUMENT, &error)
+                        .await;
+                    for chapter in chapters.iter_mut() {
+                        for receiver in &variant.receivers {
+                            chapter.record_delivery(receiver, &[], Err(error.clone()));
+                        }
+                    }
+                    continue;
                 }
+            };
+            if files.len() > 1 {
+                context.report_info(&format!(
+                    "Split document {} into {} parts",
+                    name,
+                    files.len()
+                ));
             }
-            let content = notify
-                .clone()
-                .unwrap_or(DEFAULT_NOTIFY_CONTENT_TEMPLATE.to_string())
-                .replace(HOLDER_COMIC_NAME, &element.comic_name)
-                .replace(HOLDER_CHAPTER_NAME, &element.chapter_name)
-                .replace(HOLDER_SUCCESS_COUNT, &success.to_string())
-                .replace(HOLDER_TOTAL_COUNT, receivers.len().to_string().as_str());
-            context.notify(NOTIFY_UPDATE_TITLE, &content).await;
-        } else {
-            context.report_info(&format!(
-                "Skip creating document for {}:{} in learn mode",
-                &element.comic_id, &element.chapter_id
-            ));
+            documents.push((variant, files));
+        }
+        for (variant, files) in documents {
+            deliver(
+                &mut chapters,
+                &files,
+                &variant.receivers,
+                sender,
+                max_attempts,
+                context,
+            )
+            .await;
         }
 
-        // Mark document has been sent.
-        if let Err(error) = context
-            .mark(&element.source_tag, &element.comic_id, &element.chapter_id)
-            .await
-        {
-            context.report_error(&format!(
-                "failed to mark {}:{}: {}",
-                &element.comic_id, &element.chapter_id, error
-            ));
+        if digest.is_none() {
+            let content = notify_content(notify, &chapters);
+            context.notify(NOTIFY_UPDATE_TITLE, &content).await;
         }
+
+        // Mark document has been sent, failed receivers are left pending.
+        mark(&chapters, context).await;
+        return chapters;
     });
-    join_all(futures).await;
+    updates.extend(join_all(futures).await.into_iter().flatten());
+
+    // Send summary of all updates in digest mode.
+    if let Some(digest_json) = digest {
+        let mut digest = Digest::default();
+        for chapter in &updates {
+            digest.add(chapter);
+        }
+        if !digest.is_empty() {
+            let content = digest.content(digest_json);
+            context.notify(NOTIFY_DIGEST_TITLE, &content).await;
+            for receiver in digest_json.mail() {
+                if let Err(error) = sender
+                    .send_text(receiver, NOTIFY_DIGEST_TITLE, &content)
+                    .await
+                    .map_err(|x| x.to_string())
+                {
+                    context
+                        .report_failure(
+                            Severity::Warning,
+                            FAILURE_MAIL_REJECTED,
+                            &format!("failed to send digest mail to {}: {}", receiver, error),
+                        )
+                        .await;
+                }
+            }
+        }
+    }
+
+    // Keep cache within limits for the next run.
+    match context.cache().evict().await {
+        Ok((count, size)) if count > 0 => context.report_info(&format!(
+            "Evict {} cached image(s) of {} bytes",
+            count, size
+        )),
+        Ok(_) => {}
+        Err(error) => context.report_error(&format!("failed to evict cache: {}", error)),
+    }
+}
+
+/// Send document to receivers, each part in its own mail, and record the result to chapters in
+/// the document which have not been delivered to the receiver.
+async fn deliver(
+    chapters: &mut [ChapterState],
+    files: &[PathBuf],
+    receivers: &[String],
+    sender: &Mailbox,
+    max_attempts: u32,
+    context: &Context,
+) {
+    for receiver in receivers {
+        let mut result = Ok(());
+        for file in files {
+            if let Err(error) = sender.send_file(receiver, APP_NAME_TITLEIZE, file).await {
+                result = Err(format!("{}: {}", file.display(), error));
+            }
+        }
+        for chapter in chapters.iter_mut() {
+            let delivered = chapter
+                .deliveries
+                .get(receiver)
+                .map(|x| x.delivered_at.is_some())
+                .unwrap_or(false);
+            if !delivered {
+                chapter.record_delivery(receiver, files, result.clone());
+            }
+        }
+        if let Err(error) = result {
+            // Failures are only severe when no more retry is left.
+            let severity = if chapters
+                .iter()
+                .any(|x| x.pending_receivers(max_attempts).contains(receiver))
+            {
+                Severity::Warning
+            } else {
+                Severity::Error
+            };
+            context
+                .report_failure(
+                    severity,
+                    FAILURE_MAIL_REJECTED,
+                    &format!("failed to send mail to {}: {}", receiver, error),
+                )
+                .await;
+        }
+    }
+}
+
+async fn mark(chapters: &[ChapterState], context: &Context) {
+    if chapters.is_empty() {
+        return;
+    }
+    if let Err(error) = context.mark(chapters).await {
+        context.report_error(&format!(
+            "failed to mark {}: {}",
+            chapters
+                .iter()
+                .map(|x| format!("{}:{}", x.comic_id, x.chapter_id))
+                .collect::<Vec<String>>()
+                .join(", "),
+            error
+        ));
+    }
+}
+
+fn notify_content(template: &Option<String>, chapters: &[ChapterState]) -> String {
+    let chapter = &chapters[0];
+    let chapter_names = chapters
+        .iter()
+        .map(|x| x.chapter_name.clone().unwrap_or(x.chapter_id.clone()))
+        .collect::<Vec<String>>()
+        .join(", ");
+    return template
+        .clone()
+        .unwrap_or(DEFAULT_NOTIFY_CONTENT_TEMPLATE.to_string())
+        .replace(
+            HOLDER_COMIC_NAME,
+            chapter.comic_name.as_ref().unwrap_or(&chapter.comic_id),
+        )
+        .replace(HOLDER_CHAPTER_NAME, &chapter_names)
+        .replace(HOLDER_SUCCESS_COUNT, &chapter.delivered_count().to_string())
+        .replace(HOLDER_TOTAL_COUNT, &chapter.deliveries.len().to_string());
 }
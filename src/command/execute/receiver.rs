@@ -0,0 +1,134 @@
+use super::{DocumentFormat, Element};
+use crate::util::device::{find_device, Device, DeviceJson, DEFAULT_DEVICE};
+use serde::Deserialize;
+use std::{collections::BTreeMap, error::Error, io};
+
+/// Receiver is either an address, or an object with address and options.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(super) enum ReceiverJson {
+    Address(String),
+    Options(ReceiverOptionsJson),
+}
+
+#[derive(Deserialize, Clone)]
+pub(super) struct ReceiverOptionsJson {
+    address: String,
+    format: Option<DocumentFormat>,
+    device: Option<String>,
+    scale: Option<f64>,
+    comics: Option<Vec<String>>,
+}
+
+pub(super) struct Receiver {
+    pub address: String,
+    format: Option<DocumentFormat>,
+    device_name: String,
+    device: Device,
+    scale: Option<f64>,
+    /// IDs or names of comics the receiver wants, or all comics if none.
+    comics: Option<Vec<String>>,
+}
+
+impl Receiver {
+    pub fn new(
+        json: &ReceiverJson,
+        devices: &BTreeMap<String, DeviceJson>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let options = match json {
+            ReceiverJson::Address(address) => ReceiverOptionsJson {
+                address: address.clone(),
+                format: None,
+                device: None,
+                scale: None,
+                comics: None,
+            },
+            ReceiverJson::Options(options) => options.clone(),
+        };
+        let device_name = options.device.unwrap_or(DEFAULT_DEVICE.to_string());
+        let device = find_device(&device_name, devices).ok_or(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "unknown device {} of receiver {}",
+                device_name, options.address
+            ),
+        ))?;
+        return Ok(Receiver {
+            address: options.address,
+            format: options.format,
+            device_name,
+            device,
+            scale: options.scale,
+            comics: options.comics,
+        });
+    }
+
+    fn wants(&self, element: &Element) -> bool {
+        return match &self.comics {
+            Some(comics) => comics
+                .iter()
+                .any(|x| *x == element.comic_id || *x == element.comic_name),
+            None => true,
+        };
+    }
+}
+
+/// Settings of a document, and receivers wanting it.
+pub(super) struct Variant {
+    pub format: DocumentFormat,
+    pub device_name: String,
+    pub device: Device,
+    pub scale: f64,
+    pub receivers: Vec<String>,
+}
+
+impl Variant {
+    /// Label to tell documents of variants apart, which is empty if there is only one variant
+    /// in the format.
+    pub fn label(&self, variants: &[Variant]) -> String {
+        let same_format = variants
+            .iter()
+            .filter(|x| x.format == self.format)
+            .collect::<Vec<&Variant>>();
+        if same_format.len() == 1 {
+            return "".to_string();
+        }
+        if same_format
+            .iter()
+            .filter(|x| x.device_name == self.device_name)
+            .count()
+            == 1
+        {
+            return format!(" ({})", self.device_name);
+        }
+        return format!(" ({} {})", self.device_name, self.scale);
+    }
+}
+
+/// Group receivers wanting the element by document settings in order of first appearance.
+/// Format and scale of receiver take precedence over the default ones.
+pub(super) fn variants(
+    receivers: &[Receiver],
+    element: &Element,
+    format: DocumentFormat,
+    scale: f64,
+) -> Vec<Variant> {
+    let mut variants: Vec<Variant> = vec![];
+    for receiver in receivers.iter().filter(|x| x.wants(element)) {
+        let format = receiver.format.unwrap_or(format);
+        let scale = receiver.scale.unwrap_or(scale);
+        match variants.iter_mut().find(|x| {
+            x.format == format && x.device_name == receiver.device_name && x.scale == scale
+        }) {
+            Some(variant) => variant.receivers.push(receiver.address.clone()),
+            None => variants.push(Variant {
+                format,
+                device_name: receiver.device_name.clone(),
+                device: receiver.device,
+                scale,
+                receivers: vec![receiver.address.clone()],
+            }),
+        }
+    }
+    return variants;
+}
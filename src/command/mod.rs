@@ -1 +1,3 @@
+pub(crate) mod cache;
 pub(crate) mod execute;
+pub(crate) mod search;
@@ -0,0 +1,100 @@
+use super::execute::{find_source, sources};
+use crate::{
+    context::Context,
+    util::{
+        extension::ResultExtension,
+        http::{Http, HttpJson, ProxyJson},
+        schedule::{ScheduleJson, Scheduler},
+    },
+};
+use futures::future::join_all;
+use serde::Deserialize;
+use std::{error::Error, path::PathBuf};
+
+/// Sections of the config file of execute command used by search, other sections are ignored.
+#[derive(Deserialize)]
+pub(self) struct SearchConfigJson {
+    #[serde(default)]
+    http: HttpJson,
+    #[serde(default)]
+    proxy: ProxyJson,
+    #[serde(default)]
+    concurrency: ScheduleJson,
+}
+
+impl SearchConfigJson {
+    async fn read(path: PathBuf) -> Result<SearchConfigJson, Box<dyn Error>> {
+        let json = tokio::fs::read_to_string(path).await?;
+        return serde_json::from_str::<SearchConfigJson>(json.as_str())?.into_ok();
+    }
+}
+
+pub(crate) async fn main(
+    keyword: String,
+    source: Option<String>,
+    config: Option<PathBuf>,
+    context: &mut Context,
+) {
+    if let Some(config) = config {
+        let config_json = match SearchConfigJson::read(config).await {
+            Ok(config_json) => config_json,
+            Err(error) => {
+                context.report_error(&format!("failed to parse config file: {}", error));
+                return;
+            }
+        };
+        match Http::new(
+            &config_json.http,
+            &config_json.proxy,
+            Scheduler::from(&config_json.concurrency),
+        ) {
+            Ok(http) => context.set_http(http),
+            Err(error) => {
+                context.report_error(&format!("failed to create HTTP client: {}", error));
+                return;
+            }
+        }
+    }
+    let context = &*context;
+
+    let sources = match &source {
+        Some(tag) => match find_source(tag) {
+            Some(source) => vec![source],
+            None => {
+                context.report_error(&format!("unknown source {}", tag));
+                return;
+            }
+        },
+        None => sources(),
+    };
+
+    let futures = sources.into_iter().map(|source| {
+        let keyword = keyword.as_str();
+        async move {
+            return (source.tag(), source.search(keyword, context).await);
+        }
+    });
+    let mut found = 0;
+    let mut failed = false;
+    for (tag, results) in join_all(futures).await {
+        let results = match results {
+            Ok(results) => results,
+            Err(error) => {
+                context.report_error(&format!("failed to search from {}: {}", tag, error));
+                failed = true;
+                continue;
+            }
+        };
+        for result in results {
+            found += 1;
+            context.report_info(&format!("[{}] {} {}", tag, result.comic_id, result.title));
+            context.report_info(&format!(
+                "    author: {} | status: {} | latest: {}",
+                result.author, result.status, result.latest_chapter
+            ));
+        }
+    }
+    if found == 0 && !failed {
+        context.report_info(&format!("No comic found for keyword {}", keyword));
+    }
+}
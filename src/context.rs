@@ -1,32 +1,113 @@
-use crate::{util::extension::ResultExtension, APP_NAME_TITLEIZE};
-use futures::executor::block_on;
+use crate::{
+    journal::Journal,
+    notify::{self, Notifier},
+    state::{state_key, ChapterState, State},
+    util::{
+        cache::{Cache, CacheJson},
+        http::Http,
+        shutdown::Shutdown,
+    },
+};
+use futures::future::join_all;
+use serde::Deserialize;
 use std::{
     io,
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
 };
-use tokio::fs::File as TokioFile;
 
 pub(crate) struct Context {
     debug: bool,
-    cache: PathBuf,
+    cache: Cache,
     repo: PathBuf,
-    mark: PathBuf,
-    bark: Option<String>,
+    state: Arc<Mutex<State>>,
+    state_loaded: AtomicBool,
+    state_lock: Arc<Mutex<()>>,
+    http: Http,
+    notifiers: Vec<Box<dyn Notifier>>,
+    failure: Option<FailureNotify>,
+    shutdown: Arc<Shutdown>,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Severity {
+    Warning,
+    Error,
+}
+
+impl Severity {
+    fn name(&self) -> &'static str {
+        return match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+    }
 }
 
-pub(self) const ICON_URL: &str = "https://comik-icon.aoramd.moe/icon.png";
+/// Notification of failures not less severe than the threshold.
+pub(crate) struct FailureNotify {
+    pub title: String,
+    pub template: String,
+    pub threshold: Severity,
+}
+
+pub(crate) const HOLDER_FAILURE_EVENT: &str = "%event%";
+pub(crate) const HOLDER_FAILURE_MESSAGE: &str = "%message%";
+pub(crate) const HOLDER_FAILURE_SEVERITY: &str = "%severity%";
 
 impl Context {
     pub fn new(debug: bool, cache: PathBuf, repo: PathBuf, bark: Option<String>) -> Self {
         return Context {
             debug,
-            cache: cache.clone(),
+            cache: Cache::new(cache, &CacheJson::default()),
             repo: repo.clone(),
-            mark: repo.clone().join("mark"),
-            bark,
+            state: Arc::new(Mutex::new(State::new())),
+            state_loaded: AtomicBool::new(false),
+            state_lock: Arc::new(Mutex::new(())),
+            http: Http::default(),
+            notifiers: bark.iter().map(|x| notify::bark(x)).collect(),
+            failure: None,
+            shutdown: Arc::new(Shutdown::default()),
         };
     }
 
+    pub fn http(&self) -> &Http {
+        return &self.http;
+    }
+
+    pub fn set_http(&mut self, http: Http) {
+        self.http = http;
+    }
+
+    pub fn cache(&self) -> &Cache {
+        return &self.cache;
+    }
+
+    pub fn set_cache(&mut self, cache: Cache) {
+        self.cache = cache;
+    }
+
+    pub fn add_notifiers(&mut self, notifiers: Vec<Box<dyn Notifier>>) {
+        self.notifiers.extend(notifiers);
+    }
+
+    pub fn set_failure_notify(&mut self, failure: Option<FailureNotify>) {
+        self.failure = failure;
+    }
+
+    pub fn shutdown(&self) -> Arc<Shutdown> {
+        return self.shutdown.clone();
+    }
+
+    /// Whether shutdown is requested, so that no new work should be started.
+    pub fn is_shutting_down(&self) -> bool {
+        return self.shutdown.is_requested();
+    }
+
     pub fn report_debug(&self, message: &str) {
         if self.debug {
             println!("{}", message);
@@ -41,71 +122,129 @@ impl Context {
         eprintln!("{}", message);
     }
 
+    /// Report error and notify it if the severity reaches threshold.
+    pub async fn report_failure(&self, severity: Severity, event: &str, message: &str) {
+        self.report_error(message);
+        if let Some(failure) = &self.failure {
+            if severity >= failure.threshold {
+                let content = failure
+                    .template
+                    .replace(HOLDER_FAILURE_EVENT, event)
+                    .replace(HOLDER_FAILURE_MESSAGE, message)
+                    .replace(HOLDER_FAILURE_SEVERITY, severity.name());
+                self.notify(&failure.title, &content).await;
+            }
+        }
+    }
+
+    /// Load state from repository, or migrate it from marker files of previous versions.
+    pub async fn load_state(&self) -> io::Result<()> {
+        let path = self.state_path();
+        let mark = self.repo.join("mark");
+        let state = if path.exists() {
+            State::read(&path).await?
+        } else if mark.is_dir() {
+            self.report_info("Migrate marker files to state database");
+            let state = State::migrate(&mark).await?;
+            *self.state.lock().unwrap() = state;
+            self.state_loaded.store(true, Ordering::SeqCst);
+            self.save_state().await?;
+            tokio::fs::remove_dir_all(&mark).await?;
+            return Ok(());
+        } else {
+            State::new()
+        };
+        *self.state.lock().unwrap() = state;
+        self.state_loaded.store(true, Ordering::SeqCst);
+        return Ok(());
+    }
+
     pub fn is_marked(&self, tag: &str, comic_id: &str, chapter_id: &str) -> bool {
         return self
-            .mark
-            .clone()
-            .join(&format!("{}_{}_{}", tag, comic_id, chapter_id))
-            .exists();
+            .state
+            .lock()
+            .unwrap()
+            .get(tag, comic_id, chapter_id)
+            .is_some();
+    }
+
+    pub fn pending_chapters(&self, max_attempts: u32) -> Vec<ChapterState> {
+        return self.state.lock().unwrap().pending(max_attempts);
     }
 
-    pub async fn mark(&self, tag: &str, comic_id: &str, chapter_id: &str) -> io::Result<()> {
-        let path = self.mark.clone();
-        tokio::fs::create_dir_all(path.clone()).await?;
-        let path = path.join(&format!("{}_{}_{}", tag, comic_id, chapter_id));
-        TokioFile::create(path).await?;
+    /// Record chapters into state, which is saved once for all of them.
+    pub async fn mark(&self, chapters: &[ChapterState]) -> io::Result<()> {
+        {
+            let mut state = self.state.lock().unwrap();
+            for chapter in chapters {
+                state.insert(chapter.clone());
+            }
+        }
+        self.save_state().await?;
+
+        // Journals are useless once chapters are marked.
+        for chapter in chapters {
+            match tokio::fs::remove_file(self.journal_path(&chapter.key())).await {
+                Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error),
+                _ => {}
+            }
+        }
         return Ok(());
     }
 
-    pub async fn create_image_cache(
-        &self,
-        tag: &str,
-        comic_id: &str,
-        chapter_id: &str,
-        index: usize,
-        extension: &str,
-    ) -> io::Result<PathBuf> {
-        let cache_image = self.cache.clone();
-        tokio::fs::create_dir_all(&cache_image).await?;
-        return cache_image
-            .join(format!(
-                "{}_{}_{}_{}.{}",
-                tag, comic_id, chapter_id, index, extension
-            ))
-            .into_ok();
+    /// Open journal of downloading pages of the chapter, which is kept until the chapter is
+    /// marked.
+    pub async fn open_journal(&self, tag: &str, comic_id: &str, chapter_id: &str) -> Journal {
+        return Journal::open(self.journal_path(&state_key(tag, comic_id, chapter_id))).await;
     }
 
-    pub fn document_repo_path(&self) -> &Path {
-        return &self.repo;
+    fn journal_path(&self, key: &str) -> PathBuf {
+        return self.repo.join("journal").join(format!("{}.json", key));
     }
 
-    pub async fn notify(&self, title: &str, content: &str) {
-        // Bark
-        if let Some(bark) = &self.bark {
-            let base = Path::new(bark)
-                .join(url_escape::encode_component(title).to_string())
-                .join(url_escape::encode_component(content).to_string())
-                .display()
-                .to_string();
-            let url = format!("{}?icon={}&group={}", base, ICON_URL, APP_NAME_TITLEIZE);
-            self.report_debug(&format!("notify Bark: {}", &url));
-            if let Err(error) = reqwest::get(url).await {
-                self.report_error(&format!("failed to notify Bark: {}", error));
-            }
+    /// Save state if it is loaded, which keeps records of work finished before shutdown.
+    pub async fn flush_state(&self) -> io::Result<()> {
+        if !self.state_loaded.load(Ordering::SeqCst) {
+            return Ok(());
         }
+        return self.save_state().await;
     }
-}
 
-impl Drop for Context {
-    fn drop(&mut self) {
-        block_on(async {
-            self.report_debug("start clean up context");
-            if self.cache.exists() {
-                if let Err(error) = tokio::fs::remove_dir_all(&self.cache).await {
-                    self.report_error(&format!("failed to clean up cache: {}", error));
-                }
+    async fn save_state(&self) -> io::Result<()> {
+        // Writes are serialized on blocking threads to keep the temporary file from being
+        // replaced by others, even if the caller is dropped in the middle of writing. State is
+        // serialized under the lock so that the latest state is always written last.
+        let state = self.state.clone();
+        let lock = self.state_lock.clone();
+        let repo = self.repo.clone();
+        let path = self.state_path();
+        return tokio::task::spawn_blocking(move || {
+            let _lock = lock.lock().unwrap();
+            let json = state.lock().unwrap().to_json()?;
+            std::fs::create_dir_all(&repo)?;
+            let temp = path.with_extension("json.tmp");
+            std::fs::write(&temp, json)?;
+            std::fs::rename(&temp, &path)?;
+            return Ok(());
+        })
+        .await?;
+    }
+
+    fn state_path(&self) -> PathBuf {
+        return self.repo.join("state.json");
+    }
+
+    pub fn document_repo_path(&self) -> &Path {
+        return &self.repo;
+    }
+
+    pub async fn notify(&self, title: &str, content: &str) {
+        let futures = self.notifiers.iter().map(|notifier| async move {
+            self.report_debug(&format!("notify {}: {}", notifier.name(), content));
+            if let Err(error) = notifier.notify(title, content, &self.http).await {
+                self.report_error(&format!("failed to notify {}: {}", notifier.name(), error));
             }
-            self.report_debug("complete clean up context");
         });
+        join_all(futures).await;
     }
 }
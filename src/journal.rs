@@ -0,0 +1,83 @@
+use crate::util::image::verify_image;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+};
+use tokio::sync::Mutex;
+
+#[derive(Serialize, Deserialize, Default)]
+pub(self) struct JournalJson {
+    /// Downloaded pages keyed by page index.
+    pages: BTreeMap<usize, PageJson>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub(self) struct PageJson {
+    url: String,
+    file: PathBuf,
+    size: u64,
+}
+
+/// Progress of downloading pages of a chapter, so that the download can be resumed after the
+/// process is interrupted.
+pub(crate) struct Journal {
+    path: PathBuf,
+    json: Mutex<JournalJson>,
+}
+
+impl Journal {
+    /// Open journal of the file, which is empty if the file does not exist or is broken.
+    pub async fn open(path: PathBuf) -> Self {
+        let json = tokio::fs::read_to_string(&path)
+            .await
+            .ok()
+            .and_then(|x| serde_json::from_str::<JournalJson>(&x).ok())
+            .unwrap_or_default();
+        return Journal {
+            path,
+            json: Mutex::new(json),
+        };
+    }
+
+    pub async fn len(&self) -> usize {
+        return self.json.lock().await.pages.len();
+    }
+
+    /// File of the downloaded page, which is verified with its recorded size and content.
+    pub async fn page(&self, index: usize, url: &str) -> Option<PathBuf> {
+        let page = self.json.lock().await.pages.get(&index).cloned()?;
+        if page.url != url {
+            return None;
+        }
+        let size = tokio::fs::metadata(&page.file).await.ok()?.len();
+        if size != page.size || !verify_image(&page.file).await {
+            return None;
+        }
+        return Some(page.file);
+    }
+
+    /// Record the downloaded page and save the journal.
+    pub async fn record(&self, index: usize, url: &str, file: &Path) -> io::Result<()> {
+        let size = tokio::fs::metadata(file).await?.len();
+        // Lock is held while writing to keep the file from being replaced by an older journal.
+        let mut json = self.json.lock().await;
+        json.pages.insert(
+            index,
+            PageJson {
+                url: url.to_string(),
+                file: file.to_path_buf(),
+                size,
+            },
+        );
+        let content = serde_json::to_string_pretty(&*json).map_err(io::Error::from)?;
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let temp = self.path.with_extension("json.tmp");
+        tokio::fs::write(&temp, content).await?;
+        tokio::fs::rename(&temp, &self.path).await?;
+        return Ok(());
+    }
+}
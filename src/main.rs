@@ -1,13 +1,25 @@
+#![allow(clippy::needless_return, clippy::needless_pub_self)]
+
 mod command;
 mod context;
+mod journal;
 mod model;
+mod notify;
+mod state;
 mod util;
 
 use clap::{AppSettings, Parser, Subcommand};
-use command::execute;
+use command::{
+    cache,
+    execute::{self, DocumentFormat},
+    search,
+};
 use context::Context;
-use std::path::PathBuf;
-use util::mail;
+use std::{path::PathBuf, process};
+use util::{
+    mail,
+    shutdown::{self, EXIT_CODE_ABORTED, EXIT_CODE_INTERRUPTED, SHUTDOWN_TIMEOUT},
+};
 
 pub(crate) const APP_NAME: &str = "comik";
 pub(crate) const APP_NAME_TITLEIZE: &str = "Comik";
@@ -63,7 +75,7 @@ fn default_repo_path() -> String {
 
 #[derive(Parser)]
 #[clap(author, version, about)]
-#[clap(global_setting(AppSettings::ArgRequiredElseHelp))]
+#[clap(setting(AppSettings::ArgRequiredElseHelp))]
 struct Args {
     /// Enable debug output
     #[clap(short, long, action)]
@@ -99,6 +111,7 @@ struct Args {
 
 #[derive(Subcommand)]
 enum Command {
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
     Execute {
         /// Mark but skip downloading matches
         #[clap(short, long, action)]
@@ -108,33 +121,93 @@ enum Command {
         #[clap(short, long, value_name = "factor", default_value_t = 0.9)]
         scale: f64,
 
+        /// Set default format of created document
+        #[clap(short, long, value_enum, value_name = "format", default_value = "pdf")]
+        format: DocumentFormat,
+
         /// Set config file path
         #[clap(short, long, value_parser, value_name = "file")]
         config: PathBuf,
     },
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Search {
+        /// Search comics of specified source only
+        #[clap(short, long, value_name = "tag")]
+        source: Option<String>,
+
+        /// Keyword of comic title
+        #[clap(value_name = "keyword")]
+        keyword: String,
+
+        /// Set config file path, whose HTTP and proxy options are used
+        #[clap(short, long, value_parser, value_name = "file")]
+        config: Option<PathBuf>,
+    },
+    Cache {
+        /// Remove cached images
+        #[clap(short, long, action)]
+        purge: bool,
+
+        /// Only remove cached images not used within days
+        #[clap(short, long, value_name = "days", requires = "purge")]
+        older_than: Option<u64>,
+    },
 }
 
 impl Command {
-    async fn execute(self, context: &Context) {
+    async fn execute(self, context: &mut Context) {
         match self {
             Command::Execute {
                 learn,
                 scale,
+                format,
                 config,
             } => {
                 context.report_debug("run command: execute");
                 context.report_debug(&format!("[args::execulte] learn: {}", learn));
                 context.report_debug(&format!("[args::execulte] scale: {}", scale));
+                context.report_debug(&format!("[args::execulte] format: {:?}", format));
                 context.report_debug(&format!(
                     "[args::execulte] config file path: {}",
                     config.display()
                 ));
-                if scale > 1.0 || scale < 0.0 {
+                if !(0.0..=1.0).contains(&scale) {
                     context.report_error("scale factor must be between 0.0 and 1.0");
                 } else {
-                    execute::main(learn, scale, config, context).await;
+                    execute::main(learn, scale, format, config, context).await;
                 }
             }
+            Command::Search {
+                source,
+                keyword,
+                config,
+            } => {
+                context.report_debug("run command: search");
+                context.report_debug(&format!("[args::search] keyword: {}", keyword));
+                context.report_debug(&format!(
+                    "[args::search] source: {}",
+                    source.as_deref().unwrap_or("null")
+                ));
+                context.report_debug(&format!(
+                    "[args::search] config file path: {}",
+                    config
+                        .as_ref()
+                        .map(|x| x.display().to_string())
+                        .unwrap_or("null".to_string())
+                ));
+                search::main(keyword, source, config, context).await;
+            }
+            Command::Cache { purge, older_than } => {
+                context.report_debug("run command: cache");
+                context.report_debug(&format!("[args::cache] purge: {}", purge));
+                context.report_debug(&format!(
+                    "[args::cache] older than: {}",
+                    older_than
+                        .map(|x| x.to_string())
+                        .unwrap_or("null".to_string())
+                ));
+                cache::main(purge, older_than, context).await;
+            }
         }
     }
 }
@@ -142,7 +215,7 @@ impl Command {
 #[tokio::main]
 async fn main() {
     let args: Args = Args::parse();
-    let context = Context::new(
+    let mut context = Context::new(
         args.debug,
         PathBuf::from(args.cache.as_str()),
         PathBuf::from(args.repo.as_str()),
@@ -160,5 +233,35 @@ async fn main() {
         "[args] Bark URL: {}",
         &args.bark.unwrap_or("null".to_string())
     ));
-    args.command.unwrap().execute(&context).await;
+
+    // On signal, in-flight work is allowed to finish until timeout or another signal, while no
+    // new work is started.
+    let shutdown = context.shutdown();
+    let mut command = Box::pin(args.command.unwrap().execute(&mut context));
+    tokio::select! {
+        _ = &mut command => return,
+        _ = shutdown::signal() => {}
+    }
+    shutdown.request();
+    eprintln!(
+        "Shutting down, waiting for in-flight work up to {} seconds, interrupt again to abort",
+        SHUTDOWN_TIMEOUT.as_secs()
+    );
+    let finished = tokio::select! {
+        _ = &mut command => true,
+        _ = tokio::time::sleep(SHUTDOWN_TIMEOUT) => false,
+        _ = shutdown::signal() => false,
+    };
+    drop(command);
+    if !finished {
+        context.report_error("Abort in-flight work");
+    }
+    if let Err(error) = context.flush_state().await {
+        context.report_error(&format!("failed to save state: {}", error));
+    }
+    process::exit(if finished {
+        EXIT_CODE_INTERRUPTED
+    } else {
+        EXIT_CODE_ABORTED
+    });
 }
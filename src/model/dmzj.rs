@@ -1,7 +1,14 @@
-use crate::util::extension::ResultExtension;
-use reqwest::header::{REFERER, USER_AGENT};
+use crate::util::{extension::ResultExtension, http::Http, image::sniff_extension};
 use serde::Deserialize;
-use std::{error::Error, path::Path};
+use std::{
+    error::Error,
+    io,
+    path::{Path, PathBuf},
+};
+
+pub(self) const IMAGE_REFERER: &str = "http://images.muwai.com/";
+pub(self) const IMAGE_USER_AGENT: &str =
+    "%E5%8A%A8%E6%BC%AB%E4%B9%8B%E5%AE%B6%E7%A4%BE%E5%8C%BA/27 CFNetwork/1329 Darwin/21.3.0";
 
 #[derive(Deserialize)]
 pub(self) struct ComicResp {
@@ -54,20 +61,69 @@ impl From<ComicResp> for ComicInfo {
     }
 }
 
-pub(crate) async fn search_comic(id: &str) -> Result<ComicInfo, Box<dyn Error>> {
+pub(crate) async fn search_comic(http: &Http, id: &str) -> Result<ComicInfo, Box<dyn Error>> {
     let response: ComicResp = {
-        let response = reqwest::get(format!(
-            "https://api.dmzj.com//dynamic/comicinfo/{}.json",
-            id
-        ))
-        .await?
-        .text()
-        .await?;
+        let response = http
+            .text(http.get(&format!(
+                "https://api.dmzj.com//dynamic/comicinfo/{}.json",
+                id
+            )))
+            .await?;
         serde_json::from_str(&response)?
     };
     return ComicInfo::from(response).into_ok();
 }
 
+#[derive(Deserialize)]
+pub(self) struct SearchResp {
+    pub id: u64,
+    pub title: String,
+    pub authors: String,
+    pub status: String,
+    pub last_name: String,
+}
+
+#[derive(Debug)]
+pub(crate) struct SearchInfo {
+    pub id: String,
+    pub title: String,
+    pub author: String,
+    pub status: String,
+    pub latest_chapter: String,
+}
+
+impl From<SearchResp> for SearchInfo {
+    fn from(value: SearchResp) -> Self {
+        return SearchInfo {
+            id: value.id.to_string(),
+            title: value.title,
+            author: value.authors,
+            status: value.status,
+            latest_chapter: value.last_name,
+        };
+    }
+}
+
+pub(crate) async fn search_keyword(
+    http: &Http,
+    keyword: &str,
+) -> Result<Vec<SearchInfo>, Box<dyn Error>> {
+    let response: Vec<SearchResp> = {
+        let response = http
+            .text(http.get(&format!(
+                "https://v3api.dmzj.com/search/show/0/{}/0.json",
+                url_escape::encode_component(keyword)
+            )))
+            .await?;
+        serde_json::from_str(&response)?
+    };
+    return response
+        .into_iter()
+        .map(SearchInfo::from)
+        .collect::<Vec<SearchInfo>>()
+        .into_ok();
+}
+
 #[derive(Deserialize)]
 pub(self) struct ChapterResp {
     pub page_url: Vec<String>,
@@ -87,28 +143,42 @@ impl From<ChapterResp> for ChapterInfo {
 }
 
 pub(crate) async fn search_chapter(
+    http: &Http,
     comic_id: &str,
     chapter_id: &str,
 ) -> Result<ChapterInfo, Box<dyn Error>> {
     let response: ChapterResp = {
-        let response = reqwest::get(format!(
-            "https://m.dmzj.com/chapinfo/{}/{}.html",
-            comic_id, chapter_id
-        ))
-        .await?
-        .text()
-        .await?;
+        let response = http
+            .text(http.get(&format!(
+                "https://m.dmzj.com/chapinfo/{}/{}.html",
+                comic_id, chapter_id
+            )))
+            .await?;
         serde_json::from_str(&response)?
     };
     return ChapterInfo::from(response).into_ok();
 }
 
-pub(crate) async fn download_image(file: &Path, url: &str) -> Result<(), Box<dyn Error>> {
-    let client = reqwest::Client::new();
-    let response = client.get(url)
-    .header(REFERER, "http://images.muwai.com/")
-    .header(USER_AGENT, "%E5%8A%A8%E6%BC%AB%E4%B9%8B%E5%AE%B6%E7%A4%BE%E5%8C%BA/27 CFNetwork/1329 Darwin/21.3.0")
-    .send().await?.bytes().await?;
-    tokio::fs::write(file, &response).await?;
-    return Ok(());
+/// Download image to the file named with extension of detected image type, which is written to
+/// a temporary file first to never leave a partial image behind.
+pub(crate) async fn download_image(
+    http: &Http,
+    file: &Path,
+    url: &str,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let request = http.get_with_headers(url, IMAGE_USER_AGENT, IMAGE_REFERER);
+    let (response, content_type) = http.bytes_with_type(request).await?;
+    let extension = sniff_extension(&response, content_type.as_deref()).ok_or(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "unknown image type of {} bytes with content type {}",
+            response.len(),
+            content_type.as_deref().unwrap_or("null")
+        ),
+    ))?;
+    let file = file.with_extension(extension);
+    let temp = file.with_extension(format!("{}.part", extension));
+    tokio::fs::write(&temp, &response).await?;
+    tokio::fs::rename(&temp, &file).await?;
+    return file.into_ok();
 }
@@ -0,0 +1,40 @@
+use super::{Notifier, ICON_URL};
+use crate::{util::http::Http, APP_NAME_TITLEIZE};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::{error::Error, path::Path};
+
+#[derive(Deserialize)]
+pub(crate) struct BarkJson {
+    url: String,
+}
+
+pub(super) struct BarkNotifier {
+    pub url: String,
+}
+
+impl From<&BarkJson> for BarkNotifier {
+    fn from(json: &BarkJson) -> Self {
+        return BarkNotifier {
+            url: json.url.clone(),
+        };
+    }
+}
+
+#[async_trait]
+impl Notifier for BarkNotifier {
+    fn name(&self) -> &'static str {
+        return "Bark";
+    }
+
+    async fn notify(&self, title: &str, content: &str, http: &Http) -> Result<(), Box<dyn Error>> {
+        let base = Path::new(&self.url)
+            .join(url_escape::encode_component(title).to_string())
+            .join(url_escape::encode_component(content).to_string())
+            .display()
+            .to_string();
+        let url = format!("{}?icon={}&group={}", base, ICON_URL, APP_NAME_TITLEIZE);
+        http.text(http.get(&url)).await?;
+        return Ok(());
+    }
+}
@@ -0,0 +1,51 @@
+use super::Notifier;
+use crate::util::http::Http;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+use std::error::Error;
+
+pub(self) const DEFAULT_PRIORITY: u8 = 5;
+
+#[derive(Deserialize)]
+pub(crate) struct GotifyJson {
+    server: String,
+    token: String,
+    priority: Option<u8>,
+}
+
+pub(super) struct GotifyNotifier {
+    server: String,
+    token: String,
+    priority: u8,
+}
+
+impl From<&GotifyJson> for GotifyNotifier {
+    fn from(json: &GotifyJson) -> Self {
+        return GotifyNotifier {
+            server: json.server.clone(),
+            token: json.token.clone(),
+            priority: json.priority.unwrap_or(DEFAULT_PRIORITY),
+        };
+    }
+}
+
+#[async_trait]
+impl Notifier for GotifyNotifier {
+    fn name(&self) -> &'static str {
+        return "Gotify";
+    }
+
+    async fn notify(&self, title: &str, content: &str, http: &Http) -> Result<(), Box<dyn Error>> {
+        let request = http
+            .post(&format!("{}/message", self.server.trim_end_matches('/')))
+            .header("X-Gotify-Key", &self.token)
+            .json(&json!({
+                "title": title,
+                "message": content,
+                "priority": self.priority,
+            }));
+        http.text(request).await?;
+        return Ok(());
+    }
+}
@@ -0,0 +1,56 @@
+mod bark;
+mod gotify;
+mod ntfy;
+mod telegram;
+mod webhook;
+
+use crate::util::http::Http;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::error::Error;
+
+pub(self) const ICON_URL: &str = "https://comik-icon.aoramd.moe/icon.png";
+
+#[async_trait]
+pub(crate) trait Notifier: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn notify(&self, title: &str, content: &str, http: &Http) -> Result<(), Box<dyn Error>>;
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub(crate) enum NotifierJson {
+    Bark(bark::BarkJson),
+    Ntfy(ntfy::NtfyJson),
+    Gotify(gotify::GotifyJson),
+    Telegram(telegram::TelegramJson),
+    Discord(webhook::WebhookJson),
+    Slack(webhook::WebhookJson),
+    Webhook(webhook::WebhookJson),
+}
+
+impl From<&NotifierJson> for Box<dyn Notifier> {
+    fn from(json: &NotifierJson) -> Self {
+        return match json {
+            NotifierJson::Bark(json) => Box::new(bark::BarkNotifier::from(json)),
+            NotifierJson::Ntfy(json) => Box::new(ntfy::NtfyNotifier::from(json)),
+            NotifierJson::Gotify(json) => Box::new(gotify::GotifyNotifier::from(json)),
+            NotifierJson::Telegram(json) => Box::new(telegram::TelegramNotifier::from(json)),
+            NotifierJson::Discord(json) => {
+                Box::new(webhook::WebhookNotifier::new(webhook::Style::Discord, json))
+            }
+            NotifierJson::Slack(json) => {
+                Box::new(webhook::WebhookNotifier::new(webhook::Style::Slack, json))
+            }
+            NotifierJson::Webhook(json) => {
+                Box::new(webhook::WebhookNotifier::new(webhook::Style::Json, json))
+            }
+        };
+    }
+}
+
+pub(crate) fn bark(url: &str) -> Box<dyn Notifier> {
+    return Box::new(bark::BarkNotifier {
+        url: url.to_string(),
+    });
+}
@@ -0,0 +1,60 @@
+use super::{Notifier, ICON_URL};
+use crate::util::http::Http;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+use std::error::Error;
+
+pub(self) const DEFAULT_SERVER: &str = "https://ntfy.sh";
+
+#[derive(Deserialize)]
+pub(crate) struct NtfyJson {
+    server: Option<String>,
+    topic: String,
+    token: Option<String>,
+    priority: Option<u8>,
+}
+
+pub(super) struct NtfyNotifier {
+    server: String,
+    topic: String,
+    token: Option<String>,
+    priority: Option<u8>,
+}
+
+impl From<&NtfyJson> for NtfyNotifier {
+    fn from(json: &NtfyJson) -> Self {
+        return NtfyNotifier {
+            server: json.server.clone().unwrap_or(DEFAULT_SERVER.to_string()),
+            topic: json.topic.clone(),
+            token: json.token.clone(),
+            priority: json.priority,
+        };
+    }
+}
+
+#[async_trait]
+impl Notifier for NtfyNotifier {
+    fn name(&self) -> &'static str {
+        return "ntfy";
+    }
+
+    async fn notify(&self, title: &str, content: &str, http: &Http) -> Result<(), Box<dyn Error>> {
+        // Publish as JSON to keep non-ASCII title out of headers.
+        let mut body = json!({
+            "topic": self.topic,
+            "title": title,
+            "message": content,
+            "icon": ICON_URL,
+        });
+        if let Some(priority) = self.priority {
+            body["priority"] = json!(priority);
+        }
+        let mut request = http.post(self.server.trim_end_matches('/')).json(&body);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        http.text(request).await?;
+        return Ok(());
+    }
+}
@@ -0,0 +1,53 @@
+use super::Notifier;
+use crate::util::http::Http;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+use std::error::Error;
+
+pub(self) const DEFAULT_SERVER: &str = "https://api.telegram.org";
+
+#[derive(Deserialize)]
+pub(crate) struct TelegramJson {
+    server: Option<String>,
+    token: String,
+    chat_id: String,
+}
+
+pub(super) struct TelegramNotifier {
+    server: String,
+    token: String,
+    chat_id: String,
+}
+
+impl From<&TelegramJson> for TelegramNotifier {
+    fn from(json: &TelegramJson) -> Self {
+        return TelegramNotifier {
+            server: json.server.clone().unwrap_or(DEFAULT_SERVER.to_string()),
+            token: json.token.clone(),
+            chat_id: json.chat_id.clone(),
+        };
+    }
+}
+
+#[async_trait]
+impl Notifier for TelegramNotifier {
+    fn name(&self) -> &'static str {
+        return "Telegram";
+    }
+
+    async fn notify(&self, title: &str, content: &str, http: &Http) -> Result<(), Box<dyn Error>> {
+        let request = http
+            .post(&format!(
+                "{}/bot{}/sendMessage",
+                self.server.trim_end_matches('/'),
+                self.token
+            ))
+            .json(&json!({
+                "chat_id": self.chat_id,
+                "text": format!("{}\n{}", title, content),
+            }));
+        http.text(request).await?;
+        return Ok(());
+    }
+}
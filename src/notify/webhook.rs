@@ -0,0 +1,73 @@
+use super::{Notifier, ICON_URL};
+use crate::{util::http::Http, APP_NAME_TITLEIZE};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{collections::HashMap, error::Error};
+
+#[derive(Deserialize)]
+pub(crate) struct WebhookJson {
+    url: String,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+}
+
+pub(super) enum Style {
+    Discord,
+    Slack,
+    Json,
+}
+
+pub(super) struct WebhookNotifier {
+    style: Style,
+    url: String,
+    headers: HashMap<String, String>,
+}
+
+impl WebhookNotifier {
+    pub fn new(style: Style, json: &WebhookJson) -> Self {
+        return WebhookNotifier {
+            style,
+            url: json.url.clone(),
+            headers: json.headers.clone(),
+        };
+    }
+
+    fn body(&self, title: &str, content: &str) -> Value {
+        return match self.style {
+            Style::Discord => json!({
+                "username": APP_NAME_TITLEIZE,
+                "avatar_url": ICON_URL,
+                "content": format!("**{}**\n{}", title, content),
+            }),
+            Style::Slack => json!({
+                "text": format!("*{}*\n{}", title, content),
+            }),
+            Style::Json => json!({
+                "app": APP_NAME_TITLEIZE,
+                "title": title,
+                "content": content,
+            }),
+        };
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &'static str {
+        return match self.style {
+            Style::Discord => "Discord",
+            Style::Slack => "Slack",
+            Style::Json => "webhook",
+        };
+    }
+
+    async fn notify(&self, title: &str, content: &str, http: &Http) -> Result<(), Box<dyn Error>> {
+        let mut request = http.post(&self.url).json(&self.body(title, content));
+        for (key, value) in &self.headers {
+            request = request.header(key, value);
+        }
+        http.text(request).await?;
+        return Ok(());
+    }
+}
@@ -0,0 +1,200 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub(self) const STATE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct State {
+    version: u32,
+    chapters: BTreeMap<String, ChapterState>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct ChapterState {
+    pub source: String,
+    pub comic_id: String,
+    pub chapter_id: String,
+    pub comic_name: Option<String>,
+    pub chapter_name: Option<String>,
+    /// Time in seconds since UNIX epoch when the chapter is found.
+    pub seen_at: u64,
+    /// Time in seconds since UNIX epoch when the chapter is delivered to all receivers, none if
+    /// the chapter is only learned or some deliveries are pending.
+    pub delivered_at: Option<u64>,
+    /// Delivery of the chapter document keyed by receiver address.
+    #[serde(default)]
+    pub deliveries: BTreeMap<String, Delivery>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub(crate) struct Delivery {
+    pub delivered_at: Option<u64>,
+    pub attempts: u32,
+    pub error: Option<String>,
+    /// Document files sent to the receiver, which has several parts if split by attachment size.
+    #[serde(default)]
+    pub documents: Vec<PathBuf>,
+}
+
+impl ChapterState {
+    pub fn new(source: &str, comic_id: &str, chapter_id: &str) -> Self {
+        return ChapterState {
+            source: source.to_string(),
+            comic_id: comic_id.to_string(),
+            chapter_id: chapter_id.to_string(),
+            comic_name: None,
+            chapter_name: None,
+            seen_at: now(),
+            delivered_at: None,
+            deliveries: BTreeMap::new(),
+        };
+    }
+
+    /// Add receivers the chapter will be delivered to, so that the chapter is not regarded as
+    /// delivered until all of them have received it.
+    pub fn add_receivers(&mut self, receivers: &[String]) {
+        for receiver in receivers {
+            self.deliveries.entry(receiver.clone()).or_default();
+        }
+    }
+
+    /// Record result of an attempt to send document files to the receiver.
+    pub fn record_delivery(
+        &mut self,
+        receiver: &str,
+        documents: &[PathBuf],
+        result: Result<(), String>,
+    ) {
+        let delivery = self.deliveries.entry(receiver.to_string()).or_default();
+        delivery.attempts += 1;
+        delivery.documents = documents.to_vec();
+        match result {
+            Ok(_) => {
+                delivery.delivered_at = Some(now());
+                delivery.error = None;
+            }
+            Err(error) => delivery.error = Some(error),
+        }
+        if self.delivered_at.is_none() && self.delivered_count() == self.deliveries.len() {
+            self.delivered_at = Some(now());
+        }
+    }
+
+    /// Receivers the document has not been delivered to and can still be retried. Receivers
+    /// attempted without documents are not retried, since their documents failed to be created.
+    pub fn pending_receivers(&self, max_attempts: u32) -> Vec<String> {
+        return self
+            .deliveries
+            .iter()
+            .filter(|(_, x)| {
+                x.delivered_at.is_none()
+                    && x.attempts < max_attempts
+                    && (x.attempts == 0 || !x.documents.is_empty())
+            })
+            .map(|(receiver, _)| receiver.clone())
+            .collect();
+    }
+
+    /// Pending receivers grouped by documents to retry.
+    pub fn pending_documents(&self, max_attempts: u32) -> BTreeMap<Vec<PathBuf>, Vec<String>> {
+        let mut result: BTreeMap<Vec<PathBuf>, Vec<String>> = BTreeMap::new();
+        for receiver in self.pending_receivers(max_attempts) {
+            let documents = &self.deliveries[&receiver].documents;
+            if !documents.is_empty() {
+                result.entry(documents.clone()).or_default().push(receiver);
+            }
+        }
+        return result;
+    }
+
+    pub fn delivered_count(&self) -> usize {
+        return self
+            .deliveries
+            .values()
+            .filter(|x| x.delivered_at.is_some())
+            .count();
+    }
+
+    pub fn key(&self) -> String {
+        return state_key(&self.source, &self.comic_id, &self.chapter_id);
+    }
+}
+
+impl State {
+    pub fn new() -> Self {
+        return State {
+            version: STATE_VERSION,
+            chapters: BTreeMap::new(),
+        };
+    }
+
+    pub async fn read(path: &Path) -> io::Result<Self> {
+        let json = tokio::fs::read_to_string(path).await?;
+        return serde_json::from_str::<State>(&json).map_err(io::Error::from);
+    }
+
+    /// Create state from marker files, which are empty files named "{tag}_{comic}_{chapter}"
+    /// created by previous versions.
+    pub async fn migrate(mark: &Path) -> io::Result<Self> {
+        let mut state = State::new();
+        let mut entries = tokio::fs::read_dir(mark).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let parts = name.splitn(3, '_').collect::<Vec<&str>>();
+            if parts.len() != 3 {
+                continue;
+            }
+            let time = entry
+                .metadata()
+                .await?
+                .modified()
+                .ok()
+                .and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+                .map(|x| x.as_secs())
+                .unwrap_or(0);
+            let mut chapter = ChapterState::new(parts[0], parts[1], parts[2]);
+            chapter.seen_at = time;
+            chapter.delivered_at = Some(time);
+            state.insert(chapter);
+        }
+        return Ok(state);
+    }
+
+    pub fn to_json(&self) -> io::Result<String> {
+        return serde_json::to_string_pretty(self).map_err(io::Error::from);
+    }
+
+    pub fn get(&self, tag: &str, comic_id: &str, chapter_id: &str) -> Option<&ChapterState> {
+        return self.chapters.get(&state_key(tag, comic_id, chapter_id));
+    }
+
+    /// Chapters which have pending deliveries.
+    pub fn pending(&self, max_attempts: u32) -> Vec<ChapterState> {
+        return self
+            .chapters
+            .values()
+            .filter(|x| !x.pending_receivers(max_attempts).is_empty())
+            .cloned()
+            .collect();
+    }
+
+    pub fn insert(&mut self, chapter: ChapterState) {
+        self.chapters.insert(chapter.key(), chapter);
+    }
+}
+
+pub(crate) fn state_key(tag: &str, comic_id: &str, chapter_id: &str) -> String {
+    return format!("{}_{}_{}", tag, comic_id, chapter_id);
+}
+
+pub(crate) fn now() -> u64 {
+    return SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or(0);
+}
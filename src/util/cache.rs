@@ -0,0 +1,286 @@
+use super::extension::ResultExtension;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+pub(self) const DEFAULT_MAX_SIZE_MB: u64 = 1024;
+pub(self) const DEFAULT_MAX_AGE_DAYS: u64 = 30;
+pub(self) const SECS_PER_DAY: u64 = 24 * 60 * 60;
+pub(self) const IMAGE_DIR: &str = "images";
+
+#[derive(Deserialize, Default)]
+pub(crate) struct CacheJson {
+    /// Max total size in MB of cached images.
+    max_size: Option<u64>,
+    /// Max days since cached images were used last time.
+    max_age: Option<u64>,
+}
+
+/// Persistent cache of images keyed by hash of URL, which evicts least recently used images.
+pub(crate) struct Cache {
+    dir: PathBuf,
+    max_size: u64,
+    max_age: Duration,
+}
+
+pub(crate) struct CacheEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Time the image was downloaded or used last time.
+    pub used_at: SystemTime,
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf, json: &CacheJson) -> Self {
+        return Cache {
+            dir,
+            max_size: json.max_size.unwrap_or(DEFAULT_MAX_SIZE_MB) * 1024 * 1024,
+            max_age: days(json.max_age.unwrap_or(DEFAULT_MAX_AGE_DAYS)),
+        };
+    }
+
+    pub fn dir(&self) -> &Path {
+        return &self.dir;
+    }
+
+    /// Path of cached image of the URL without extension, which is added by the detected image
+    /// type.
+    pub async fn path(&self, url: &str) -> io::Result<PathBuf> {
+        let key = key(url);
+        let dir = self.dir.join(IMAGE_DIR).join(&key[..2]);
+        tokio::fs::create_dir_all(&dir).await?;
+        return dir.join(key).into_ok();
+    }
+
+    /// Find image of the URL downloaded before, and mark it as recently used.
+    pub async fn find(&self, url: &str) -> Option<PathBuf> {
+        let key = key(url);
+        let mut entries = tokio::fs::read_dir(self.dir.join(IMAGE_DIR).join(&key[..2]))
+            .await
+            .ok()?;
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Images derived from the cached image are named with more extensions.
+            let original = name
+                .strip_prefix(&key)
+                .and_then(|x| x.strip_prefix('.'))
+                .map(|x| !x.contains('.'))
+                .unwrap_or(false);
+            if original {
+                let path = entry.path();
+                let _ = File::options()
+                    .write(true)
+                    .open(&path)
+                    .and_then(|x| x.set_modified(SystemTime::now()));
+                return Some(path);
+            }
+        }
+        return None;
+    }
+
+    /// All cached images including derived ones, the least recently used first.
+    pub async fn entries(&self) -> io::Result<Vec<CacheEntry>> {
+        let mut result = vec![];
+        let root = self.dir.join(IMAGE_DIR);
+        if !root.is_dir() {
+            return result.into_ok();
+        }
+        let mut dirs = tokio::fs::read_dir(root).await?;
+        while let Some(dir) = dirs.next_entry().await? {
+            if !dir.file_type().await?.is_dir() {
+                continue;
+            }
+            let mut files = tokio::fs::read_dir(dir.path()).await?;
+            while let Some(file) = files.next_entry().await? {
+                let metadata = file.metadata().await?;
+                result.push(CacheEntry {
+                    path: file.path(),
+                    size: metadata.len(),
+                    used_at: metadata.modified()?,
+                });
+            }
+        }
+        result.sort_by_key(|x| x.used_at);
+        return result.into_ok();
+    }
+
+    /// Remove images not used within max age, and then the least recently used images until
+    /// total size is within max size. Return count and total size of removed images.
+    pub async fn evict(&self) -> io::Result<(usize, u64)> {
+        let entries = self.entries().await?;
+        let now = SystemTime::now();
+        let mut size = entries.iter().map(|x| x.size).sum::<u64>();
+        let mut removed = vec![];
+        for entry in entries {
+            let expired = now
+                .duration_since(entry.used_at)
+                .map(|x| x > self.max_age)
+                .unwrap_or(false);
+            if !expired && size <= self.max_size {
+                break;
+            }
+            size -= entry.size;
+            removed.push(entry);
+        }
+        return remove(removed).await;
+    }
+
+    /// Remove images not used within the days, or all images if none.
+    pub async fn purge(&self, older_than: Option<u64>) -> io::Result<(usize, u64)> {
+        let now = SystemTime::now();
+        let removed = self
+            .entries()
+            .await?
+            .into_iter()
+            .filter(|x| match older_than {
+                Some(older_than) => now
+                    .duration_since(x.used_at)
+                    .map(|x| x > days(older_than))
+                    .unwrap_or(false),
+                None => true,
+            })
+            .collect();
+        return remove(removed).await;
+    }
+}
+
+async fn remove(entries: Vec<CacheEntry>) -> io::Result<(usize, u64)> {
+    let count = entries.len();
+    let mut size = 0;
+    for entry in entries {
+        tokio::fs::remove_file(&entry.path).await?;
+        size += entry.size;
+    }
+    return (count, size).into_ok();
+}
+
+fn key(url: &str) -> String {
+    return Sha256::digest(url.as_bytes())
+        .iter()
+        .map(|x| format!("{:02x}", x))
+        .collect();
+}
+
+pub(crate) fn days(days: u64) -> Duration {
+    return Duration::from_secs(days * SECS_PER_DAY);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cache in a new temporary directory, with images of the URLs used the seconds ago.
+    async fn cache(name: &str, max_size: u64, images: &[(&str, &str, u64)]) -> Cache {
+        let dir = std::env::temp_dir().join(format!("comik-cache-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let cache = Cache {
+            dir,
+            max_size,
+            max_age: days(1),
+        };
+        for (url, extension, secs) in images {
+            let path = cache.path(url).await.unwrap().with_extension(extension);
+            std::fs::write(&path, [0u8; 100]).unwrap();
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(SystemTime::now() - Duration::from_secs(*secs))
+                .unwrap();
+        }
+        return cache;
+    }
+
+    fn names(entries: &[CacheEntry]) -> Vec<String> {
+        return entries
+            .iter()
+            .map(|x| x.path.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+    }
+
+    #[tokio::test]
+    async fn entries_by_last_use() {
+        let cache = cache(
+            "entries",
+            0,
+            &[("a", "png", 10), ("b", "png", 30), ("c", "png", 20)],
+        )
+        .await;
+        let entries = cache.entries().await.unwrap();
+        assert_eq!(
+            names(&entries),
+            vec![
+                format!("{}.png", key("b")),
+                format!("{}.png", key("c")),
+                format!("{}.png", key("a"))
+            ]
+        );
+        std::fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn evict_until_within_max_size() {
+        let cache = cache(
+            "evict",
+            250,
+            &[("a", "png", 10), ("b", "png", 30), ("c", "png", 20)],
+        )
+        .await;
+        assert_eq!(cache.evict().await.unwrap(), (1, 100));
+        assert!(cache.find("b").await.is_none());
+        assert!(cache.find("a").await.is_some());
+        assert!(cache.find("c").await.is_some());
+        assert_eq!(cache.evict().await.unwrap(), (0, 0));
+        std::fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn evict_expired() {
+        let expired = SECS_PER_DAY * 2;
+        let cache = cache("expired", 1000, &[("a", "png", 10), ("b", "png", expired)]).await;
+        assert_eq!(cache.evict().await.unwrap(), (1, 100));
+        assert!(cache.find("b").await.is_none());
+        std::fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn find_original_image() {
+        let cache = cache(
+            "find",
+            0,
+            &[("a", "100x200.q85.jpg", 10), ("a", "webp", 30)],
+        )
+        .await;
+        let path = cache.find("a").await.unwrap();
+        assert_eq!(
+            path.file_name().unwrap().to_string_lossy(),
+            format!("{}.webp", key("a"))
+        );
+        // Found image becomes the most recently used one.
+        assert_eq!(cache.entries().await.unwrap().last().unwrap().path, path);
+        assert!(cache.find("b").await.is_none());
+        std::fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn find_no_derived_image() {
+        let cache = cache("derived", 0, &[("a", "converted.png", 10)]).await;
+        assert!(cache.find("a").await.is_none());
+        std::fs::remove_dir_all(cache.dir()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn purge_older_than() {
+        let old = SECS_PER_DAY * 3;
+        let cache = cache("purge", 0, &[("a", "png", 10), ("b", "png", old)]).await;
+        assert_eq!(cache.purge(Some(2)).await.unwrap(), (1, 100));
+        assert_eq!(cache.purge(None).await.unwrap(), (1, 100));
+        assert!(cache.entries().await.unwrap().is_empty());
+        std::fs::remove_dir_all(cache.dir()).unwrap();
+    }
+}
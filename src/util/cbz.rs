@@ -0,0 +1,93 @@
+use super::{
+    document::{pages, DocumentChapter},
+    image::embedded_format,
+    xml::escape,
+};
+use std::{
+    error::Error,
+    io::{Cursor, Write},
+    path::{Path, PathBuf},
+};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+pub async fn create_cbz_from_images(
+    name: &str,
+    parent_dir: &Path,
+    chapters: &[DocumentChapter],
+    series: &str,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let mut cbz = ZipWriter::new(Cursor::new(Vec::new()));
+    // Images are compressed already.
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let images = pages(chapters);
+    let mut pages = String::new();
+    let page_count = images.len();
+    for (index, (bookmark, image_path)) in images.into_iter().enumerate() {
+        let image_data = tokio::fs::read(image_path).await?;
+        let extension = embedded_format(&image_data)?.extensions_str()[0];
+        // Comic readers sort entries by name, so keep index zero-padded.
+        cbz.start_file(format!("{:04}.{}", index + 1, extension), stored)?;
+        cbz.write_all(&image_data)?;
+        let cover = if index == 0 {
+            r#" Type="FrontCover""#
+        } else {
+            ""
+        };
+        let bookmark = match bookmark {
+            Some(bookmark) => format!(" Bookmark=\"{}\"", escape(bookmark)),
+            None => String::new(),
+        };
+        pages.push_str(&format!(
+            "    <Page Image=\"{}\"{}{}/>\n",
+            index, cover, bookmark
+        ));
+    }
+
+    // Number is only meaningful for document of a single chapter.
+    let (title, number) = match chapters {
+        [chapter] => (
+            chapter.title.clone(),
+            chapter_number(&chapter.title)
+                .map(|x| format!("  <Number>{}</Number>\n", x))
+                .unwrap_or_default(),
+        ),
+        [first, .., last] => (format!("{} - {}", first.title, last.title), String::new()),
+        [] => (String::new(), String::new()),
+    };
+    cbz.start_file("ComicInfo.xml", deflated)?;
+    cbz.write_all(
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<ComicInfo xmlns:xsd="http://www.w3.org/2001/XMLSchema" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+  <Title>{title}</Title>
+  <Series>{series}</Series>
+{number}  <PageCount>{count}</PageCount>
+  <Pages>
+{pages}  </Pages>
+</ComicInfo>
+"#,
+            title = escape(&title),
+            series = escape(series),
+            number = number,
+            count = page_count,
+            pages = pages,
+        )
+        .as_bytes(),
+    )?;
+
+    let result = parent_dir.join(name);
+    tokio::fs::write(result.clone(), cbz.finish()?.into_inner()).await?;
+    return Ok(result);
+}
+
+// Find the first number in chapter title like "第10.5话".
+fn chapter_number(chapter: &str) -> Option<String> {
+    let start = chapter.find(|c: char| c.is_ascii_digit())?;
+    let number = chapter[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect::<String>();
+    return Some(number.trim_end_matches('.').to_string());
+}
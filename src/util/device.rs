@@ -0,0 +1,81 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+pub(crate) const DEFAULT_DEVICE: &str = "a5";
+pub(self) const DEFAULT_DPI: f64 = 300.0;
+pub(self) const MM_PER_INCH: f64 = 25.4;
+
+/// Built-in device profiles, which are screen resolution in pixels and DPI.
+pub(self) const DEVICES: [(&str, u32, u32, f64); 6] = [
+    // Same as the fixed A5 page of previous versions.
+    (DEFAULT_DEVICE, 1748, 2480, 300.0),
+    ("kindle-paperwhite", 1236, 1648, 300.0),
+    ("kindle-oasis", 1264, 1680, 300.0),
+    ("kindle-scribe", 1860, 2480, 300.0),
+    ("kobo-libra", 1264, 1680, 300.0),
+    ("ipad", 1640, 2360, 264.0),
+];
+
+#[derive(Deserialize, Clone)]
+pub(crate) struct DeviceJson {
+    width: u32,
+    height: u32,
+    dpi: Option<f64>,
+    margin: Option<u32>,
+}
+
+/// Page of documents, all lengths are in pixels.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) struct Device {
+    pub width: u32,
+    pub height: u32,
+    pub dpi: f64,
+    pub margin: u32,
+}
+
+impl From<&DeviceJson> for Device {
+    fn from(json: &DeviceJson) -> Self {
+        let width = json.width.max(1);
+        let height = json.height.max(1);
+        return Device {
+            width,
+            height,
+            dpi: json.dpi.filter(|x| *x > 0.0).unwrap_or(DEFAULT_DPI),
+            margin: json.margin.unwrap_or(0).min((width.min(height) - 1) / 2),
+        };
+    }
+}
+
+impl Default for Device {
+    fn default() -> Self {
+        return find_device(DEFAULT_DEVICE, &BTreeMap::new()).unwrap();
+    }
+}
+
+impl Device {
+    /// Convert length in pixels to millimeters.
+    pub fn mm(&self, pixels: u32) -> f64 {
+        return pixels as f64 / self.dpi * MM_PER_INCH;
+    }
+
+    /// Size of the content box inside margins.
+    pub fn content_size(&self) -> (u32, u32) {
+        return (self.width - self.margin * 2, self.height - self.margin * 2);
+    }
+}
+
+/// Find device profile by name, custom profiles take precedence over built-in ones.
+pub(crate) fn find_device(name: &str, custom: &BTreeMap<String, DeviceJson>) -> Option<Device> {
+    if let Some(json) = custom.get(name) {
+        return Some(Device::from(json));
+    }
+    return DEVICES
+        .iter()
+        .find(|x| x.0 == name)
+        .map(|(_, width, height, dpi)| Device {
+            width: *width,
+            height: *height,
+            dpi: *dpi,
+            margin: 0,
+        });
+}
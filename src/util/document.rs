@@ -0,0 +1,131 @@
+use std::path::PathBuf;
+
+/// Chapter of a document, which may contain several chapters of the same comic.
+#[derive(Clone)]
+pub(crate) struct DocumentChapter {
+    pub title: String,
+    pub images: Vec<PathBuf>,
+}
+
+/// Flatten images of chapters in order, paired with chapter title on the first page of each
+/// chapter.
+pub(crate) fn pages(chapters: &[DocumentChapter]) -> Vec<(Option<&str>, &PathBuf)> {
+    return chapters
+        .iter()
+        .flat_map(|chapter| {
+            chapter
+                .images
+                .iter()
+                .enumerate()
+                .map(move |(index, image)| {
+                    let title = if index == 0 {
+                        Some(chapter.title.as_str())
+                    } else {
+                        None
+                    };
+                    (title, image)
+                })
+        })
+        .collect();
+}
+
+/// Split chapters into parts with nearly the same page count in order. A chapter across parts
+/// appears in each of them with the same title.
+pub(crate) fn split(chapters: &[DocumentChapter], count: usize) -> Vec<Vec<DocumentChapter>> {
+    let total = chapters.iter().map(|x| x.images.len()).sum::<usize>();
+    let size = total.div_ceil(count.max(1));
+    let mut parts: Vec<Vec<DocumentChapter>> = vec![];
+    let mut part: Vec<DocumentChapter> = vec![];
+    let mut pages = 0;
+    for chapter in chapters {
+        for image in &chapter.images {
+            if pages == size {
+                parts.push(std::mem::take(&mut part));
+                pages = 0;
+            }
+            match part.last_mut() {
+                Some(last) if last.title == chapter.title => last.images.push(image.clone()),
+                _ => part.push(DocumentChapter {
+                    title: chapter.title.clone(),
+                    images: vec![image.clone()],
+                }),
+            }
+            pages += 1;
+        }
+    }
+    if !part.is_empty() {
+        parts.push(part);
+    }
+    return parts;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chapter(title: &str, pages: usize) -> DocumentChapter {
+        return DocumentChapter {
+            title: title.to_string(),
+            images: (0..pages)
+                .map(|x| PathBuf::from(format!("{}-{}.jpg", title, x)))
+                .collect(),
+        };
+    }
+
+    fn layout(parts: &[Vec<DocumentChapter>]) -> Vec<Vec<(String, usize)>> {
+        return parts
+            .iter()
+            .map(|part| {
+                part.iter()
+                    .map(|x| (x.title.clone(), x.images.len()))
+                    .collect()
+            })
+            .collect();
+    }
+
+    #[test]
+    fn split_evenly() {
+        let parts = split(&[chapter("1", 3), chapter("2", 3)], 2);
+        assert_eq!(
+            layout(&parts),
+            vec![vec![("1".to_string(), 3)], vec![("2".to_string(), 3)]]
+        );
+    }
+
+    #[test]
+    fn split_chapter_across_parts() {
+        let parts = split(&[chapter("1", 2), chapter("2", 4)], 2);
+        assert_eq!(
+            layout(&parts),
+            vec![
+                vec![("1".to_string(), 2), ("2".to_string(), 1)],
+                vec![("2".to_string(), 3)]
+            ]
+        );
+        assert_eq!(parts[0][1].images[0], PathBuf::from("2-0.jpg"));
+        assert_eq!(parts[1][0].images[0], PathBuf::from("2-1.jpg"));
+    }
+
+    #[test]
+    fn split_into_more_parts_than_pages() {
+        let parts = split(&[chapter("1", 3)], 5);
+        assert_eq!(parts.len(), 3);
+        assert!(parts.iter().all(|x| x.len() == 1 && x[0].images.len() == 1));
+    }
+
+    #[test]
+    fn split_without_pages() {
+        assert!(split(&[], 2).is_empty());
+        assert!(split(&[chapter("1", 0)], 0).is_empty());
+    }
+
+    #[test]
+    fn pages_with_titles() {
+        let chapters = [chapter("1", 2), chapter("2", 1)];
+        let titles = pages(&chapters)
+            .into_iter()
+            .map(|(title, _)| title)
+            .collect::<Vec<Option<&str>>>();
+        assert_eq!(titles, vec![Some("1"), None, Some("2")]);
+    }
+}
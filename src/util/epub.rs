@@ -0,0 +1,269 @@
+use super::{
+    device::Device,
+    document::{pages, DocumentChapter},
+    image::embedded_format,
+    xml::escape,
+};
+use printpdf::image_crate::io::Reader;
+use sha2::{Digest, Sha256};
+use std::{
+    error::Error,
+    io::{Cursor, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+pub async fn create_epub_from_images(
+    name: &str,
+    parent_dir: &Path,
+    chapters: &[DocumentChapter],
+    scale: f64,
+    device: &Device,
+) -> Result<PathBuf, Box<dyn Error>> {
+    // Viewport is the screen of device in pixels.
+    let (content_width, content_height) = device.content_size();
+    let title = escape(
+        &Path::new(name)
+            .file_stem()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or(name.to_string()),
+    );
+
+    let mut epub = ZipWriter::new(Cursor::new(Vec::new()));
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    // The mimetype entry must be the first one and must not be compressed.
+    epub.start_file("mimetype", stored)?;
+    epub.write_all(b"application/epub+zip")?;
+    epub.start_file("META-INF/container.xml", deflated)?;
+    epub.write_all(CONTAINER_XML.as_bytes())?;
+
+    let mut manifest = String::new();
+    let mut spine = String::new();
+    let mut toc = String::new();
+    let images = pages(chapters);
+    for (index, (heading, image_path)) in images.into_iter().enumerate() {
+        let image_data = tokio::fs::read(image_path).await?;
+        let image_format = embedded_format(&image_data)?;
+        let media_type = image_format.to_mime_type();
+        let image_name = format!(
+            "images/{:04}.{}",
+            index + 1,
+            image_format.extensions_str()[0]
+        );
+        let page_name = format!("page-{:04}.xhtml", index + 1);
+
+        // Calculate image box in viewport.
+        let (image_width, image_height) =
+            Reader::with_format(Cursor::new(&image_data), image_format).into_dimensions()?;
+        let width_factor = content_width as f64 / image_width as f64;
+        let height_factor = content_height as f64 / image_height as f64;
+        let scale_factor = width_factor.min(height_factor) * scale;
+        let width = (image_width as f64 * scale_factor).round() as u32;
+        let height = (image_height as f64 * scale_factor).round() as u32;
+        let left = (device.width as i64 - width as i64) / 2;
+        let top = (device.height as i64 - height as i64) / 2;
+
+        epub.start_file(format!("OEBPS/{}", image_name), stored)?;
+        epub.write_all(&image_data)?;
+        epub.start_file(format!("OEBPS/{}", page_name), deflated)?;
+        epub.write_all(
+            format!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+  <title>{title}</title>
+  <meta name="viewport" content="width={vw}, height={vh}"/>
+  <style>body {{ margin: 0; padding: 0; width: {vw}px; height: {vh}px; }}</style>
+</head>
+<body>
+  <img src="{image}" alt="" style="position: absolute; left: {left}px; top: {top}px; width: {width}px; height: {height}px;"/>
+</body>
+</html>
+"#,
+                title = title,
+                vw = device.width,
+                vh = device.height,
+                image = image_name,
+                left = left,
+                top = top,
+                width = width,
+                height = height,
+            )
+            .as_bytes(),
+        )?;
+
+        let cover = if index == 0 {
+            r#" properties="cover-image""#
+        } else {
+            ""
+        };
+        manifest.push_str(&format!(
+            "    <item id=\"image-{index}\" href=\"{image}\" media-type=\"{media_type}\"{cover}/>\n",
+            index = index + 1,
+            image = image_name,
+            media_type = media_type,
+            cover = cover,
+        ));
+        manifest.push_str(&format!(
+            "    <item id=\"page-{index}\" href=\"{page}\" media-type=\"application/xhtml+xml\"/>\n",
+            index = index + 1,
+            page = page_name,
+        ));
+        spine.push_str(&format!("    <itemref idref=\"page-{}\"/>\n", index + 1));
+        if let Some(heading) = heading {
+            toc.push_str(&format!(
+                "      <li><a href=\"{}\">{}</a></li>\n",
+                page_name,
+                escape(heading)
+            ));
+        }
+    }
+
+    epub.start_file("OEBPS/nav.xhtml", deflated)?;
+    epub.write_all(
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+  <title>{title}</title>
+</head>
+<body>
+  <nav epub:type="toc" id="toc">
+    <ol>
+{toc}    </ol>
+  </nav>
+</body>
+</html>
+"#,
+            title = title,
+            toc = toc,
+        )
+        .as_bytes(),
+    )?;
+
+    let modified = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    epub.start_file("OEBPS/content.opf", deflated)?;
+    epub.write_all(
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id" prefix="rendition: http://www.idpf.org/vocab/rendition/#">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="book-id">urn:comik:{identifier}</dc:identifier>
+    <dc:title>{title}</dc:title>
+    <dc:language>zh</dc:language>
+    <meta property="dcterms:modified">{timestamp}</meta>
+    <meta property="rendition:layout">pre-paginated</meta>
+    <meta property="rendition:orientation">portrait</meta>
+    <meta property="rendition:spread">none</meta>
+    <meta name="fixed-layout" content="true"/>
+    <meta name="book-type" content="comic"/>
+    <meta name="original-resolution" content="{vw}x{vh}"/>
+  </metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+{manifest}  </manifest>
+  <spine>
+{spine}  </spine>
+</package>
+"#,
+            identifier = identifier(name, modified),
+            title = title,
+            timestamp = format_timestamp(modified),
+            vw = device.width,
+            vh = device.height,
+            manifest = manifest,
+            spine = spine,
+        )
+        .as_bytes(),
+    )?;
+
+    let result = parent_dir.join(name);
+    tokio::fs::write(result.clone(), epub.finish()?.into_inner()).await?;
+    return Ok(result);
+}
+
+/// Unique identifier of the publication, since parts and variants of the same document are
+/// created in the same second.
+fn identifier(name: &str, modified: u64) -> String {
+    return Sha256::digest(format!("{}:{}", name, modified).as_bytes())
+        .iter()
+        .map(|x| format!("{:02x}", x))
+        .collect();
+}
+
+// Format seconds since UNIX epoch as "YYYY-MM-DDThh:mm:ssZ".
+fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let seconds = secs % 86400;
+
+    // Convert days to civil date, see http://howardhinnant.github.io/date_algorithms.html.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    return format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifier_of_parts() {
+        assert_ne!(
+            identifier("Comic 1 Part 1.epub", 0),
+            identifier("Comic 1 Part 2.epub", 0)
+        );
+        assert_ne!(identifier("Comic 1.epub", 0), identifier("Comic 1.epub", 1));
+        assert_eq!(identifier("Comic 1.epub", 0).len(), 64);
+    }
+
+    #[test]
+    fn format_epoch() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn format_leap_days() {
+        // 2000 is a leap year as a multiple of 400, while 2100 is not as a multiple of 100.
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_timestamp(951_868_800), "2000-03-01T00:00:00Z");
+        assert_eq!(format_timestamp(1_709_251_199), "2024-02-29T23:59:59Z");
+        assert_eq!(format_timestamp(4_107_542_399), "2100-02-28T23:59:59Z");
+        assert_eq!(format_timestamp(4_107_542_400), "2100-03-01T00:00:00Z");
+    }
+
+    #[test]
+    fn format_year_boundary() {
+        assert_eq!(format_timestamp(1_704_067_199), "2023-12-31T23:59:59Z");
+        assert_eq!(format_timestamp(1_704_067_200), "2024-01-01T00:00:00Z");
+        assert_eq!(format_timestamp(1_234_567_890), "2009-02-13T23:31:30Z");
+    }
+}
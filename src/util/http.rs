@@ -0,0 +1,225 @@
+use super::{extension::ResultExtension, schedule::Scheduler};
+use reqwest::{
+    header::{CONTENT_TYPE, REFERER, USER_AGENT},
+    Client, NoProxy, Proxy, RequestBuilder, Response, StatusCode,
+};
+use serde::Deserialize;
+use std::{error::Error, future::Future, time::Duration};
+
+pub(self) const DEFAULT_TIMEOUT_SECS: u64 = 30;
+pub(self) const DEFAULT_RETRIES: u32 = 3;
+pub(self) const DEFAULT_BACKOFF_MILLIS: u64 = 1000;
+pub(self) const MAX_BACKOFF_SECS: u64 = 5 * 60;
+pub(self) const POOL_IDLE_TIMEOUT_SECS: u64 = 90;
+pub(self) const TCP_KEEPALIVE_SECS: u64 = 60;
+
+#[derive(Deserialize, Default)]
+pub(crate) struct HttpJson {
+    timeout: Option<u64>,
+    retries: Option<u32>,
+    backoff: Option<u64>,
+    user_agent: Option<String>,
+    referer: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+pub(crate) struct ProxyJson {
+    http: Option<String>,
+    https: Option<String>,
+    socks5: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    #[serde(default)]
+    no_proxy: Vec<String>,
+}
+
+impl ProxyJson {
+    fn proxies(&self) -> Result<Vec<Proxy>, Box<dyn Error>> {
+        let mut proxies = vec![];
+        if let Some(url) = &self.http {
+            proxies.push(Proxy::http(url)?);
+        }
+        if let Some(url) = &self.https {
+            proxies.push(Proxy::https(url)?);
+        }
+        // Requests not matched by HTTP or HTTPS proxy go through SOCKS5 proxy.
+        if let Some(url) = &self.socks5 {
+            proxies.push(Proxy::all(url)?);
+        }
+        let no_proxy = NoProxy::from_string(&self.no_proxy.join(","));
+        return proxies
+            .into_iter()
+            .map(|proxy| {
+                let proxy = match &self.username {
+                    Some(username) => {
+                        proxy.basic_auth(username, self.password.as_deref().unwrap_or(""))
+                    }
+                    None => proxy,
+                };
+                return proxy.no_proxy(no_proxy.clone());
+            })
+            .collect::<Vec<Proxy>>()
+            .into_ok();
+    }
+}
+
+/// HTTP layer shared by all requests, which reuses pooled connections of a single client and
+/// retries transient errors with exponential backoff.
+pub(crate) struct Http {
+    client: Client,
+    user_agent: Option<String>,
+    referer: Option<String>,
+    timeout: Duration,
+    retries: u32,
+    backoff: Duration,
+    scheduler: Scheduler,
+}
+
+impl Default for Http {
+    fn default() -> Self {
+        return Http::new(
+            &HttpJson::default(),
+            &ProxyJson::default(),
+            Scheduler::default(),
+        )
+        .expect("failed to create default HTTP client");
+    }
+}
+
+impl Http {
+    pub fn new(
+        json: &HttpJson,
+        proxy: &ProxyJson,
+        scheduler: Scheduler,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut client = Client::builder()
+            .pool_idle_timeout(Duration::from_secs(POOL_IDLE_TIMEOUT_SECS))
+            .tcp_keepalive(Duration::from_secs(TCP_KEEPALIVE_SECS));
+        for proxy in proxy.proxies()? {
+            client = client.proxy(proxy);
+        }
+        return Ok(Http {
+            client: client.build()?,
+            user_agent: json.user_agent.clone(),
+            referer: json.referer.clone(),
+            timeout: Duration::from_secs(json.timeout.unwrap_or(DEFAULT_TIMEOUT_SECS)),
+            retries: json.retries.unwrap_or(DEFAULT_RETRIES),
+            backoff: Duration::from_millis(json.backoff.unwrap_or(DEFAULT_BACKOFF_MILLIS)),
+            scheduler,
+        });
+    }
+
+    pub fn scheduler(&self) -> &Scheduler {
+        return &self.scheduler;
+    }
+
+    /// Create GET request with configured headers.
+    pub fn get(&self, url: &str) -> RequestBuilder {
+        let mut request = self.client.get(url);
+        if let Some(user_agent) = &self.user_agent {
+            request = request.header(USER_AGENT, user_agent);
+        }
+        if let Some(referer) = &self.referer {
+            request = request.header(REFERER, referer);
+        }
+        return request;
+    }
+
+    /// Create POST request with configured headers.
+    pub fn post(&self, url: &str) -> RequestBuilder {
+        let mut request = self.client.post(url);
+        if let Some(user_agent) = &self.user_agent {
+            request = request.header(USER_AGENT, user_agent);
+        }
+        return request;
+    }
+
+    /// Create GET request with configured headers, or source-specific headers if not configured.
+    pub fn get_with_headers(&self, url: &str, user_agent: &str, referer: &str) -> RequestBuilder {
+        return self
+            .client
+            .get(url)
+            .header(USER_AGENT, self.user_agent.as_deref().unwrap_or(user_agent))
+            .header(REFERER, self.referer.as_deref().unwrap_or(referer));
+    }
+
+    pub async fn text(&self, request: RequestBuilder) -> Result<String, Box<dyn Error>> {
+        return self
+            .execute(request, |response| async move { response.text().await })
+            .await;
+    }
+
+    /// Read response body with its Content-Type header.
+    pub async fn bytes_with_type(
+        &self,
+        request: RequestBuilder,
+    ) -> Result<(Vec<u8>, Option<String>), Box<dyn Error>> {
+        return self
+            .execute(request, |response| async move {
+                let content_type = response
+                    .headers()
+                    .get(CONTENT_TYPE)
+                    .and_then(|x| x.to_str().ok())
+                    .map(|x| x.to_string());
+                response.bytes().await.map(|x| (x.to_vec(), content_type))
+            })
+            .await;
+    }
+
+    async fn execute<T, F, R>(&self, request: RequestBuilder, read: F) -> Result<T, Box<dyn Error>>
+    where
+        F: Fn(Response) -> R,
+        R: Future<Output = reqwest::Result<T>>,
+    {
+        let built = request.try_clone().and_then(|x| x.build().ok());
+        let host = built
+            .as_ref()
+            .and_then(|x| x.url().host_str().map(|x| x.to_string()));
+        // Requests like POST may have taken effect even if the response failed, which are only
+        // retried if the connection failed.
+        let idempotent = built
+            .as_ref()
+            .map(|x| x.method().is_idempotent())
+            .unwrap_or(false);
+        let mut attempt = 0;
+        loop {
+            // Permit is released before waiting for retry.
+            let permit = self.scheduler.request(host.as_deref()).await;
+            let result = match request
+                .try_clone()
+                .ok_or("request cannot be retried")?
+                .timeout(self.timeout)
+                .send()
+                .await
+                .and_then(|x| x.error_for_status())
+            {
+                Ok(response) => read(response).await,
+                Err(error) => Err(error),
+            };
+            drop(permit);
+            match result {
+                Ok(value) => return Ok(value),
+                Err(error) if attempt < self.retries && is_transient(&error, idempotent) => {
+                    let delay = 2u32
+                        .checked_pow(attempt)
+                        .and_then(|x| self.backoff.checked_mul(x))
+                        .unwrap_or(Duration::MAX)
+                        .min(Duration::from_secs(MAX_BACKOFF_SECS));
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(error) => return Err(Box::new(error)),
+            }
+        }
+    }
+}
+
+fn is_transient(error: &reqwest::Error, idempotent: bool) -> bool {
+    if !idempotent {
+        return error.is_connect();
+    }
+    if let Some(status) = error.status() {
+        return status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS;
+    }
+    return error.is_timeout() || error.is_connect() || error.is_request() || error.is_body();
+}
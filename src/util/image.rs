@@ -0,0 +1,511 @@
+use super::{device::Device, document::DocumentChapter, extension::ResultExtension};
+use printpdf::image_crate::{
+    codecs::jpeg::JpegEncoder, guess_format, imageops::FilterType, io::Reader, DynamicImage,
+    ImageFormat,
+};
+use serde::Deserialize;
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufWriter, Error as IoError, ErrorKind},
+    path::{Path, PathBuf},
+};
+use tokio::io::AsyncReadExt;
+
+pub(self) const DEFAULT_QUALITY: u8 = 85;
+pub(self) const DEFAULT_CROP_TOLERANCE: u8 = 24;
+/// Max ratio of pixels out of tolerance in a uniform line, which allows specks of scans.
+pub(self) const CROP_NOISE_RATIO: f64 = 0.01;
+/// Length of leading bytes to detect image format.
+pub(self) const MAGIC_SIZE: u64 = 64;
+
+#[derive(Deserialize, Default, Clone)]
+pub(crate) struct ImageJson {
+    #[serde(default)]
+    grayscale: bool,
+    width: Option<u32>,
+    height: Option<u32>,
+    quality: Option<u8>,
+    contrast: Option<f32>,
+    gamma: Option<f32>,
+    crop: Option<CropJson>,
+    spread: Option<Spread>,
+}
+
+/// Handling of double-page spreads, which are images wider than high.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Spread {
+    /// Split into right page followed by left page, for manga.
+    SplitRtl,
+    /// Split into left page followed by right page.
+    SplitLtr,
+    /// Rotate 90 degrees clockwise to fill the portrait page.
+    Rotate,
+    Keep,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub(crate) struct CropJson {
+    #[serde(default = "default_crop_enabled")]
+    enabled: bool,
+    tolerance: Option<u8>,
+}
+
+fn default_crop_enabled() -> bool {
+    return true;
+}
+
+impl CropJson {
+    fn tolerance(&self) -> Option<u8> {
+        return if self.enabled {
+            Some(self.tolerance.unwrap_or(DEFAULT_CROP_TOLERANCE))
+        } else {
+            None
+        };
+    }
+}
+
+/// Process images before embedding them into documents, which re-encodes them as JPEG.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) struct ImageProcess {
+    grayscale: bool,
+    width: Option<u32>,
+    height: Option<u32>,
+    quality: u8,
+    contrast: Option<f32>,
+    gamma: Option<f32>,
+    /// Tolerance of uniform borders to trim.
+    crop: Option<u8>,
+    spread: Spread,
+}
+
+impl From<&ImageJson> for ImageProcess {
+    fn from(json: &ImageJson) -> Self {
+        return ImageProcess {
+            grayscale: json.grayscale,
+            width: json.width.filter(|x| *x > 0),
+            height: json.height.filter(|x| *x > 0),
+            quality: json.quality.unwrap_or(DEFAULT_QUALITY).clamp(1, 100),
+            contrast: json.contrast.filter(|x| *x != 0.0),
+            gamma: json.gamma.filter(|x| *x > 0.0 && *x != 1.0),
+            crop: json.crop.and_then(|x| x.tolerance()),
+            spread: json.spread.unwrap_or(Spread::Keep),
+        };
+    }
+}
+
+impl ImageProcess {
+    /// Whether images are embedded as downloaded.
+    pub fn is_identity(&self) -> bool {
+        return *self == ImageProcess::from(&ImageJson::default());
+    }
+
+    /// Override border trimming with comic-specific option.
+    pub fn with_crop(&self, crop: Option<&CropJson>) -> Self {
+        return match crop {
+            Some(crop) => ImageProcess {
+                crop: crop.tolerance(),
+                ..*self
+            },
+            None => *self,
+        };
+    }
+
+    /// Override handling of spreads with comic-specific option.
+    pub fn with_spread(&self, spread: Option<Spread>) -> Self {
+        return ImageProcess {
+            spread: spread.unwrap_or(self.spread),
+            ..*self
+        };
+    }
+
+    /// Fit images into content box of the device unless target resolution is set, which does
+    /// nothing if images are embedded as downloaded.
+    pub fn for_device(&self, device: &Device) -> Self {
+        if self.is_identity() {
+            return *self;
+        }
+        let (width, height) = device.content_size();
+        return ImageProcess {
+            width: self.width.or(Some(width)),
+            height: self.height.or(Some(height)),
+            ..*self
+        };
+    }
+
+    /// Process images of chapters, and return chapters with processed images.
+    pub async fn process_chapters(
+        &self,
+        chapters: &[DocumentChapter],
+    ) -> Result<Vec<DocumentChapter>, Box<dyn Error>> {
+        let mut result = vec![];
+        for chapter in chapters {
+            let mut images = vec![];
+            for image in &chapter.images {
+                if self.is_identity() {
+                    images.push(convert(image).await?);
+                } else {
+                    images.extend(self.process(image).await?);
+                }
+            }
+            result.push(DocumentChapter {
+                title: chapter.title.clone(),
+                images,
+            });
+        }
+        return result.into_ok();
+    }
+
+    /// Process image and save it beside the source image, which results in two images if it is
+    /// a spread split into pages.
+    pub async fn process(&self, source: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        let process = *self;
+        let source = source.to_path_buf();
+        let result = tokio::task::spawn_blocking(move || {
+            return process.process_blocking(&source).map_err(|x| x.to_string());
+        })
+        .await?;
+        return result.map_err(|x| x.into());
+    }
+
+    fn process_blocking(&self, source: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        let mut image = Reader::open(source)?.with_guessed_format()?.decode()?;
+        if let Some(tolerance) = self.crop {
+            image = trim(image, tolerance);
+        }
+        let pages = if image.width() > image.height() {
+            let (width, height) = (image.width(), image.height());
+            let left = image.crop_imm(0, 0, width / 2, height);
+            let right = image.crop_imm(width / 2, 0, width - width / 2, height);
+            match self.spread {
+                Spread::SplitRtl => vec![right, left],
+                Spread::SplitLtr => vec![left, right],
+                Spread::Rotate => vec![image.rotate90()],
+                Spread::Keep => vec![image],
+            }
+        } else {
+            vec![image]
+        };
+
+        let mut result = vec![];
+        let count = pages.len();
+        for (index, image) in pages.into_iter().enumerate() {
+            let image = self.adjust(image);
+
+            // Images processed with different options are saved to different files.
+            let file = source.with_extension(format!(
+                "{}x{}{}{}{}.q{}.jpg",
+                image.width(),
+                image.height(),
+                if self.grayscale { "g" } else { "" },
+                self.crop.map(|x| format!("c{}", x)).unwrap_or_default(),
+                if count > 1 {
+                    format!("p{}", index + 1)
+                } else {
+                    "".to_string()
+                },
+                self.quality
+            ));
+            let mut writer = BufWriter::new(File::create(&file)?);
+            JpegEncoder::new_with_quality(&mut writer, self.quality).encode(
+                image.as_bytes(),
+                image.width(),
+                image.height(),
+                image.color(),
+            )?;
+            result.push(file);
+        }
+        return result.into_ok();
+    }
+
+    /// Downscale image and adjust its colors.
+    fn adjust(&self, mut image: DynamicImage) -> DynamicImage {
+        // Only downscale to fit the target resolution while keeping aspect ratio.
+        let width = self.width.unwrap_or(u32::MAX);
+        let height = self.height.unwrap_or(u32::MAX);
+        if image.width() > width || image.height() > height {
+            image = image.resize(width, height, FilterType::Triangle);
+        }
+        if let Some(contrast) = self.contrast {
+            image = image.adjust_contrast(contrast);
+        }
+        let mut image = if self.grayscale {
+            DynamicImage::ImageLuma8(image.into_luma8())
+        } else {
+            DynamicImage::ImageRgb8(image.into_rgb8())
+        };
+        if let Some(gamma) = self.gamma {
+            let table = (0..=255u8)
+                .map(|x| ((x as f32 / 255.0).powf(1.0 / gamma) * 255.0).round() as u8)
+                .collect::<Vec<u8>>();
+            match &mut image {
+                DynamicImage::ImageLuma8(buffer) => {
+                    buffer.iter_mut().for_each(|x| *x = table[*x as usize])
+                }
+                DynamicImage::ImageRgb8(buffer) => {
+                    buffer.iter_mut().for_each(|x| *x = table[*x as usize])
+                }
+                _ => {}
+            }
+        }
+        return image;
+    }
+}
+
+/// Detect extension of image from its magic bytes, or from its MIME type if the content is not
+/// recognized.
+pub(crate) fn sniff_extension(bytes: &[u8], content_type: Option<&str>) -> Option<&'static str> {
+    let format = guess_format(bytes).ok().or_else(|| {
+        let mime = content_type?.split(';').next()?.trim().to_lowercase();
+        return ImageFormat::from_mime_type(mime);
+    })?;
+    return format.extensions_str().first().copied();
+}
+
+/// Whether the file exists and starts with magic bytes of a known image format.
+pub(crate) async fn verify_image(file: &Path) -> bool {
+    return match read_magic(file).await {
+        Ok(bytes) => guess_format(&bytes).is_ok(),
+        Err(_) => false,
+    };
+}
+
+/// Read leading bytes of the file, which are enough to detect image format.
+async fn read_magic(file: &Path) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![];
+    tokio::fs::File::open(file)
+        .await?
+        .take(MAGIC_SIZE)
+        .read_to_end(&mut bytes)
+        .await?;
+    return bytes.into_ok();
+}
+
+/// Check that the image can be decoded by this build, since some decoders like AVIF are optional.
+pub(crate) async fn check_decodable(file: &Path) -> Result<(), Box<dyn Error>> {
+    let format = guess_format(&read_magic(file).await?)?;
+    if !format.reading_enabled() {
+        return Err(Box::new(IoError::new(
+            ErrorKind::Unsupported,
+            format!("image format {:?} is not supported by this build", format),
+        )));
+    }
+    return Ok(());
+}
+
+/// Detect format of image from its magic bytes, which must be embeddable into documents as is.
+pub(crate) fn embedded_format(bytes: &[u8]) -> Result<ImageFormat, Box<dyn Error>> {
+    let format = guess_format(bytes)?;
+    if !is_embeddable(format) {
+        return Err(Box::new(IoError::new(
+            ErrorKind::Unsupported,
+            format!("unsupported image format {:?}", format),
+        )));
+    }
+    return format.into_ok();
+}
+
+fn is_embeddable(format: ImageFormat) -> bool {
+    return format == ImageFormat::Png || format == ImageFormat::Jpeg;
+}
+
+/// Convert image to PNG beside the source image unless it can be embedded as is, like WebP, GIF,
+/// AVIF and BMP images.
+pub(crate) async fn convert(source: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let source = source.to_path_buf();
+    let result = tokio::task::spawn_blocking(move || {
+        let convert = || -> Result<PathBuf, Box<dyn Error>> {
+            let reader = Reader::open(&source)?.with_guessed_format()?;
+            if reader.format().map(is_embeddable).unwrap_or(false) {
+                return source.clone().into_ok();
+            }
+            let result = source.with_extension("converted.png");
+            reader
+                .decode()?
+                .save_with_format(&result, ImageFormat::Png)?;
+            return result.into_ok();
+        };
+        return convert().map_err(|x| x.to_string());
+    })
+    .await?;
+    return result.map_err(|x| x.into());
+}
+
+/// Trim uniform borders, whose lines have nearly the same luma as the outermost line within the
+/// tolerance. Image is kept as is if it is blank.
+fn trim(image: DynamicImage, tolerance: u8) -> DynamicImage {
+    let luma = image.to_luma8();
+    let (width, height) = luma.dimensions();
+    if width < 3 || height < 3 {
+        return image;
+    }
+    let row = |y: u32| {
+        (0..width)
+            .map(|x| luma.get_pixel(x, y)[0])
+            .collect::<Vec<u8>>()
+    };
+    let column = |x: u32| {
+        (0..height)
+            .map(|y| luma.get_pixel(x, y)[0])
+            .collect::<Vec<u8>>()
+    };
+    let top = uniform_lines((0..height).map(row), tolerance);
+    let bottom = uniform_lines((0..height).rev().map(row), tolerance);
+    let left = uniform_lines((0..width).map(column), tolerance);
+    let right = uniform_lines((0..width).rev().map(column), tolerance);
+    if top + bottom >= height || left + right >= width || top + bottom + left + right == 0 {
+        return image;
+    }
+    return image.crop_imm(left, top, width - left - right, height - top - bottom);
+}
+
+/// Count leading uniform lines, whose background is the median luma of the first line.
+fn uniform_lines(lines: impl Iterator<Item = Vec<u8>>, tolerance: u8) -> u32 {
+    let mut count = 0;
+    let mut background = None;
+    for line in lines {
+        let background = *background.get_or_insert_with(|| {
+            let mut sorted = line.clone();
+            sorted.sort_unstable();
+            sorted[sorted.len() / 2]
+        });
+        let noise = line
+            .iter()
+            .filter(|x| x.abs_diff(background) > tolerance)
+            .count();
+        if noise as f64 > line.len() as f64 * CROP_NOISE_RATIO {
+            break;
+        }
+        count += 1;
+    }
+    return count;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use printpdf::image_crate::{GrayImage, Luma};
+
+    /// Image of the background color with a black box inside the bounds.
+    fn boxed(
+        width: u32,
+        height: u32,
+        background: u8,
+        (left, top, right, bottom): (u32, u32, u32, u32),
+    ) -> DynamicImage {
+        return DynamicImage::ImageLuma8(GrayImage::from_fn(width, height, |x, y| {
+            if x >= left && x < right && y >= top && y < bottom {
+                Luma([0])
+            } else {
+                Luma([background])
+            }
+        }));
+    }
+
+    #[test]
+    fn trim_white_borders() {
+        let image = trim(boxed(20, 30, 255, (3, 5, 17, 26)), DEFAULT_CROP_TOLERANCE);
+        assert_eq!((image.width(), image.height()), (14, 21));
+    }
+
+    #[test]
+    fn trim_black_frame() {
+        // Content of white box inside a black frame, which is the reverse of boxed image.
+        let mut image = boxed(20, 20, 0, (0, 0, 0, 0)).into_luma8();
+        for y in 4..16 {
+            for x in 2..18 {
+                image.put_pixel(x, y, Luma([255]));
+            }
+        }
+        let image = trim(DynamicImage::ImageLuma8(image), DEFAULT_CROP_TOLERANCE);
+        assert_eq!((image.width(), image.height()), (16, 12));
+    }
+
+    #[test]
+    fn trim_within_tolerance() {
+        // Border of light gray is uniform with white within tolerance.
+        let mut image = boxed(10, 10, 255, (2, 2, 8, 8)).into_luma8();
+        image.put_pixel(0, 5, Luma([240]));
+        let image = trim(DynamicImage::ImageLuma8(image), DEFAULT_CROP_TOLERANCE);
+        assert_eq!((image.width(), image.height()), (6, 6));
+    }
+
+    #[test]
+    fn trim_keeps_blank_image() {
+        let image = trim(boxed(10, 10, 255, (0, 0, 0, 0)), DEFAULT_CROP_TOLERANCE);
+        assert_eq!((image.width(), image.height()), (10, 10));
+    }
+
+    #[test]
+    fn trim_keeps_tiny_image() {
+        let image = trim(boxed(2, 10, 255, (0, 4, 1, 6)), DEFAULT_CROP_TOLERANCE);
+        assert_eq!((image.width(), image.height()), (2, 10));
+    }
+
+    #[test]
+    fn trim_keeps_image_without_border() {
+        let image = trim(boxed(10, 10, 255, (0, 0, 10, 10)), DEFAULT_CROP_TOLERANCE);
+        assert_eq!((image.width(), image.height()), (10, 10));
+    }
+
+    #[test]
+    fn uniform_lines_allow_specks() {
+        // Two specks in a line of 200 pixels are within the noise ratio, but three are not.
+        let mut speck = vec![255u8; 200];
+        speck[10] = 0;
+        speck[20] = 0;
+        let mut specks = speck.clone();
+        specks[30] = 0;
+        let lines = vec![vec![255u8; 200], speck, specks, vec![255u8; 200]];
+        assert_eq!(uniform_lines(lines.into_iter(), DEFAULT_CROP_TOLERANCE), 2);
+    }
+
+    const PNG_MAGIC: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+    const JPEG_MAGIC: &[u8] = b"\xff\xd8\xff\xe0\0\x10JFIF\0";
+
+    #[test]
+    fn sniff_magic_bytes() {
+        assert_eq!(sniff_extension(PNG_MAGIC, None), Some("png"));
+        assert_eq!(sniff_extension(JPEG_MAGIC, None), Some("jpg"));
+        assert_eq!(sniff_extension(b"RIFF\0\0\0\0WEBPVP8 ", None), Some("webp"));
+        assert_eq!(sniff_extension(b"GIF89a", None), Some("gif"));
+    }
+
+    #[test]
+    fn sniff_magic_bytes_before_content_type() {
+        assert_eq!(sniff_extension(PNG_MAGIC, Some("image/jpeg")), Some("png"));
+        assert_eq!(
+            sniff_extension(JPEG_MAGIC, Some("application/octet-stream")),
+            Some("jpg")
+        );
+    }
+
+    #[test]
+    fn sniff_content_type_fallback() {
+        assert_eq!(
+            sniff_extension(b"unknown", Some("image/webp")),
+            Some("webp")
+        );
+        assert_eq!(
+            sniff_extension(b"unknown", Some(" Image/PNG ; charset=binary")),
+            Some("png")
+        );
+    }
+
+    #[test]
+    fn sniff_error_page() {
+        let page = b"<!DOCTYPE html><html><body>404 Not Found</body></html>";
+        assert_eq!(
+            sniff_extension(page, Some("text/html; charset=utf-8")),
+            None
+        );
+        assert_eq!(sniff_extension(page, None), None);
+        assert_eq!(sniff_extension(b"", Some("")), None);
+    }
+
+    #[test]
+    fn uniform_lines_of_nothing() {
+        assert_eq!(uniform_lines(std::iter::empty(), DEFAULT_CROP_TOLERANCE), 0);
+    }
+}
@@ -6,21 +6,35 @@ use lettre::{
     SmtpTransport, Transport,
 };
 use serde::Deserialize;
-use std::{cell::RefCell, error::Error, path::Path};
+use std::{error::Error, io, path::Path};
+
+/// Room reserved for headers and MIME boundaries of a mail.
+pub(self) const MAIL_HEADER_SIZE: u64 = 64 * 1024;
 
 #[derive(Deserialize)]
 pub(crate) struct MailboxJson {
     address: String,
     host: String,
     password: String,
+    max_attachment_size: Option<u64>,
 }
 
 pub(crate) struct Mailbox {
     address: String,
-    transport: RefCell<SmtpTransport>,
+    transport: SmtpTransport,
+    max_attachment_size: Option<u64>,
 }
 
 impl Mailbox {
+    /// Max size in bytes of a file to attach, which is encoded as base64 in lines of 76
+    /// characters, and must leave room for headers within the limit of the mail server.
+    pub fn max_attachment_size(&self) -> Option<u64> {
+        return self
+            .max_attachment_size
+            .map(|x| x.saturating_sub(MAIL_HEADER_SIZE) / 4 * 3 / 78 * 76)
+            .filter(|x| *x > 0);
+    }
+
     pub async fn send_file(
         &self,
         to: &str,
@@ -39,19 +53,70 @@ impl Mailbox {
             .multipart(MultiPart::alternative().singlepart(
                 Attachment::new(file.file_name().unwrap().to_string_lossy().to_string()).body(
                     tokio::fs::read(file).await?,
-                    ContentType::parse("application/pdf").unwrap(),
+                    ContentType::parse(content_type(file)).unwrap(),
                 ),
             ))?;
 
-        self.transport.borrow_mut().send(&mail)?;
+        return self.send(mail).await;
+    }
+
+    pub async fn send_text(
+        &self,
+        to: &str,
+        subject: &str,
+        text: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let from = LettreMailBox::new(
+            Some(APP_NAME_TITLEIZE.to_string()),
+            self.address.as_str().parse::<Address>()?,
+        );
+        let to = LettreMailBox::new(None, to.parse::<Address>()?);
+        let mail = Message::builder()
+            .from(from)
+            .to(to)
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(text.to_string())?;
+
+        return self.send(mail).await;
+    }
+
+    /// Send mail on a blocking thread, which keeps the runtime responsive while talking to the
+    /// SMTP server.
+    async fn send(&self, mail: Message) -> Result<(), Box<dyn Error>> {
+        let transport = self.transport.clone();
+        tokio::task::spawn_blocking(move || {
+            return transport.send(&mail).map(|_| ()).map_err(|x| x.to_string());
+        })
+        .await??;
         return Ok(());
     }
 }
 
+fn content_type(file: &Path) -> &'static str {
+    return match file.extension().and_then(|x| x.to_str()) {
+        Some("pdf") => "application/pdf",
+        Some("epub") => "application/epub+zip",
+        Some("cbz") => "application/vnd.comicbook+zip",
+        _ => "application/octet-stream",
+    };
+}
+
 impl TryFrom<&MailboxJson> for Mailbox {
     type Error = Box<dyn Error>;
 
     fn try_from(json: &MailboxJson) -> Result<Self, Box<dyn Error>> {
+        let max_attachment_size = match json.max_attachment_size {
+            Some(size) => Some(
+                size.checked_mul(1024 * 1024)
+                    .filter(|x| *x > MAIL_HEADER_SIZE)
+                    .ok_or(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("invalid max attachment size {} MB", size),
+                    ))?,
+            ),
+            None => None,
+        };
         let transport = SmtpTransport::starttls_relay(json.host.as_str())?
             .credentials(Credentials::new(
                 json.address.clone(),
@@ -61,7 +126,8 @@ impl TryFrom<&MailboxJson> for Mailbox {
             .build();
         return Ok(Mailbox {
             address: json.address.clone(),
-            transport: RefCell::new(transport),
+            transport,
+            max_attachment_size,
         });
     }
 }
@@ -1,3 +1,13 @@
+pub(crate) mod cache;
+pub(crate) mod cbz;
+pub(crate) mod device;
+pub(crate) mod document;
+pub(crate) mod epub;
 pub(crate) mod extension;
+pub(crate) mod http;
+pub(crate) mod image;
 pub(crate) mod mail;
 pub(crate) mod pdf;
+pub(crate) mod schedule;
+pub(crate) mod shutdown;
+pub(crate) mod xml;
@@ -1,56 +1,83 @@
-use image_crate::codecs::{jpeg::JpegDecoder, png::PngDecoder};
+use super::{
+    device::Device,
+    document::{pages, DocumentChapter},
+    image::embedded_format,
+};
+use image_crate::{
+    codecs::{jpeg::JpegDecoder, png::PngDecoder},
+    ColorType, ImageDecoder, ImageFormat,
+};
 use printpdf::*;
 use printpdf::{Image, Mm, PdfDocument};
 use std::{
     error::Error,
     io::Cursor,
-    io::Error as IoError,
     path::{Path, PathBuf},
 };
 
 const PDF_DPI: f64 = 300.0;
-const A5_WIDTH: Mm = Mm(148.0);
-const A5_HEIGHT: Mm = Mm(210.0);
 
 pub async fn create_pdf_from_images(
     name: &str,
     parent_dir: &Path,
-    images: &[PathBuf],
+    chapters: &[DocumentChapter],
     scale: f64,
+    device: &Device,
 ) -> Result<PathBuf, Box<dyn Error>> {
-    let (pdf, page, layer) = PdfDocument::new(name, A5_WIDTH, A5_HEIGHT, "layer");
+    let page_width = Mm(device.mm(device.width));
+    let page_height = Mm(device.mm(device.height));
+    let (content_width, content_height) = device.content_size();
+    let (content_width, content_height) =
+        (Mm(device.mm(content_width)), Mm(device.mm(content_height)));
+    let (pdf, page, layer) = PdfDocument::new(name, page_width, page_height, "layer");
 
     let mut current_page = page;
     let mut current_layer = layer;
 
+    // Mark the first page of each chapter in outline.
+    let images = pages(chapters);
     let page_count = images.len();
-    for (index, image_path) in images.iter().enumerate() {
-        let extension = image_path.extension().and_then(|x| x.to_str());
+    for (index, (bookmark, image_path)) in images.into_iter().enumerate() {
+        if let Some(bookmark) = bookmark {
+            pdf.add_bookmark(bookmark, current_page);
+        }
         let current_ref = pdf.get_page(current_page).get_layer(current_layer);
         let image_data = Cursor::new(tokio::fs::read(image_path).await?);
-        let image = if extension == Some("png") {
+        let image = if embedded_format(image_data.get_ref())? == ImageFormat::Png {
             Image::try_from(PngDecoder::new(image_data)?)?
-        } else if extension == Some("jpg") || extension == Some("jpeg") {
-            Image::try_from(JpegDecoder::new(image_data)?)?
         } else {
-            return Err(Box::new(IoError::new(
-                std::io::ErrorKind::Unsupported,
-                "unsupported image format",
-            )));
+            let decoder = JpegDecoder::new(image_data.clone())?;
+            if decoder.color_type() == ColorType::L8 {
+                // Embed grayscale JPEG as is to keep its compression, while other color types are
+                // decoded since printpdf disables color transform of DCT filter.
+                let (width, height) = decoder.dimensions();
+                Image::from(ImageXObject::new(
+                    Px(width as usize),
+                    Px(height as usize),
+                    ColorSpace::Greyscale,
+                    ColorBits::Bit8,
+                    false,
+                    Some(ImageFilter::DCT),
+                    None,
+                    image_data.into_inner(),
+                ))
+            } else {
+                Image::try_from(decoder)?
+            }
         };
 
         // Calculate transform.
         let image_width = Mm::from(image.image.width.into_pt(PDF_DPI));
         let image_height = Mm::from(image.image.height.into_pt(PDF_DPI));
-        let width_factor = A5_WIDTH / image_width;
-        let height_factor = A5_HEIGHT / image_height;
+        let width_factor = content_width / image_width;
+        let height_factor = content_height / image_height;
         let scale_factor = if width_factor < height_factor {
             width_factor * scale
         } else {
             height_factor * scale
         };
-        let translate_x = (A5_WIDTH - image_width * scale_factor) / 2.0;
-        let translate_y = (A5_HEIGHT - image_height * scale_factor) / 2.0;
+        let translate_x = (page_width - image_width * scale_factor) / 2.0;
+        let translate_y = (page_height - image_height * scale_factor) / 2.0;
         let transform = ImageTransform {
             translate_x: Some(translate_x),
             translate_y: Some(translate_y),
@@ -64,7 +91,7 @@ pub async fn create_pdf_from_images(
 
         // Do not add a empty page if this is the last image.
         if index + 1 < page_count {
-            let (page, layer) = pdf.add_page(A5_WIDTH, A5_HEIGHT, "layer");
+            let (page, layer) = pdf.add_page(page_width, page_height, "layer");
             current_page = page;
             current_layer = layer;
         }
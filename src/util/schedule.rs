@@ -0,0 +1,79 @@
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, SemaphorePermit};
+
+pub(self) const DEFAULT_CONNECTIONS: usize = 16;
+pub(self) const DEFAULT_CONNECTIONS_PER_HOST: usize = 4;
+pub(self) const DEFAULT_CHAPTERS: usize = 4;
+
+#[derive(Deserialize, Default)]
+pub(crate) struct ScheduleJson {
+    connections: Option<usize>,
+    connections_per_host: Option<usize>,
+    chapters: Option<usize>,
+}
+
+/// Limit count of concurrent requests and chapter fetches.
+pub(crate) struct Scheduler {
+    connections: Semaphore,
+    connections_per_host: usize,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+    chapters: Semaphore,
+}
+
+pub(crate) struct RequestPermit<'a> {
+    _global: SemaphorePermit<'a>,
+    _host: Option<OwnedSemaphorePermit>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        return Scheduler::from(&ScheduleJson::default());
+    }
+}
+
+impl From<&ScheduleJson> for Scheduler {
+    fn from(json: &ScheduleJson) -> Self {
+        return Scheduler {
+            connections: Semaphore::new(json.connections.unwrap_or(DEFAULT_CONNECTIONS).max(1)),
+            connections_per_host: json
+                .connections_per_host
+                .unwrap_or(DEFAULT_CONNECTIONS_PER_HOST)
+                .max(1),
+            hosts: Mutex::new(HashMap::new()),
+            chapters: Semaphore::new(json.chapters.unwrap_or(DEFAULT_CHAPTERS).max(1)),
+        };
+    }
+}
+
+impl Scheduler {
+    /// Wait until a request to the host is allowed, which lasts until the permit is dropped.
+    pub async fn request(&self, host: Option<&str>) -> RequestPermit<'_> {
+        let host = match host {
+            Some(host) => {
+                let semaphore = self
+                    .hosts
+                    .lock()
+                    .unwrap()
+                    .entry(host.to_string())
+                    .or_insert_with(|| Arc::new(Semaphore::new(self.connections_per_host)))
+                    .clone();
+                // Semaphores are never closed.
+                Some(semaphore.acquire_owned().await.unwrap())
+            }
+            None => None,
+        };
+        return RequestPermit {
+            _global: self.connections.acquire().await.unwrap(),
+            _host: host,
+        };
+    }
+
+    /// Wait until fetching a chapter is allowed, which lasts until the permit is dropped.
+    pub async fn chapter(&self) -> SemaphorePermit<'_> {
+        return self.chapters.acquire().await.unwrap();
+    }
+}
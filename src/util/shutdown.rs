@@ -0,0 +1,66 @@
+use std::{
+    future::pending,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+/// Time to wait for in-flight work after shutdown is requested, before it is aborted.
+pub(crate) const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+/// Exit code when in-flight work finished after an interrupt signal, same as shells use for
+/// SIGINT.
+pub(crate) const EXIT_CODE_INTERRUPTED: i32 = 130;
+/// Exit code when in-flight work was aborted by timeout or a second interrupt signal.
+pub(crate) const EXIT_CODE_ABORTED: i32 = 131;
+
+/// Flag of shutdown requested by signals, which stops scheduling new work.
+#[derive(Default)]
+pub(crate) struct Shutdown {
+    requested: AtomicBool,
+}
+
+impl Shutdown {
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        return self.requested.load(Ordering::SeqCst);
+    }
+}
+
+/// Wait until SIGINT (Ctrl-C) or SIGTERM is received. A signal failed to listen for is reported
+/// and never received, instead of being taken as received.
+#[cfg(unix)]
+pub(crate) async fn signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+    let terminate = async {
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                if terminate.recv().await.is_none() {
+                    pending::<()>().await;
+                }
+            }
+            Err(error) => {
+                eprintln!("failed to listen for SIGTERM: {}", error);
+                pending::<()>().await;
+            }
+        }
+    };
+    tokio::select! {
+        _ = interrupt() => {}
+        _ = terminate => {}
+    }
+}
+
+/// Wait until Ctrl-C is received.
+#[cfg(not(unix))]
+pub(crate) async fn signal() {
+    interrupt().await;
+}
+
+async fn interrupt() {
+    if let Err(error) = tokio::signal::ctrl_c().await {
+        eprintln!("failed to listen for Ctrl-C: {}", error);
+        pending::<()>().await;
+    }
+}